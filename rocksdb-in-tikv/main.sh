@@ -1,2 +1,2 @@
-#./run.sh bin/rocksdb-in-tikv cases/default_jobs
-./run.sh bin/rocksdb-in-tikv cases/compaction-style/jobs
\ No newline at end of file
+#bin/rocksdb-in-tikv -N plan cases/default_plan.toml
+bin/rocksdb-in-tikv -N plan cases/compaction-style/plan.toml
@@ -0,0 +1,116 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use rocksdb::DB;
+
+use super::dataset::DATASET_FILE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestoreBy {
+    // Hardlink the SST files and copy the rest, falls back to copying when the
+    // checkpoint is on another filesystem.
+    Hardlink,
+    Copy,
+}
+
+impl RestoreBy {
+    pub fn parse(s: &str) -> Result<RestoreBy, String> {
+        match s {
+            "hardlink" => Ok(RestoreBy::Hardlink),
+            "copy" => Ok(RestoreBy::Copy),
+            _ => Err(format!("{} is not a valid restore method, expect hardlink or copy", s)),
+        }
+    }
+}
+
+// Take a checkpoint of `db` into `dir`, which must not exist. The memtables are flushed
+// first, so the checkpoint has no WAL to replay.
+pub fn create(db: &DB, db_path: &str, dir: &str) -> Result<(), String> {
+    let timer = Instant::now();
+    let mut checkpointer = try!(db.new_checkpointer());
+    try!(checkpointer.create_at(Path::new(dir), 0)
+        .map_err(|e| format!("create checkpoint {} failed: {}", dir, e)));
+    // RocksDB does not know about the dataset of the load phase.
+    let dataset = Path::new(db_path).join(DATASET_FILE);
+    if dataset.exists() {
+        try!(fs::copy(&dataset, Path::new(dir).join(DATASET_FILE))
+            .map_err(|e| format!("copy {} failed: {}", dataset.display(), e)));
+    }
+    let elapsed = timer.elapsed();
+    println!("checkpoint {} created in {} ms",
+             dir,
+             elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000);
+    Ok(())
+}
+
+// Restore the checkpoint in `dir` into `db_path`, which must not exist. Only SST files
+// are immutable, the MANIFEST, CURRENT and the others are always copied so the trial
+// can not change the checkpoint through them.
+pub fn restore(dir: &str, db_path: &str, by: RestoreBy) -> Result<(), String> {
+    let timer = Instant::now();
+    if Path::new(db_path).exists() {
+        return Err(format!("restore {} into {} failed: it exists", dir, db_path));
+    }
+    try!(fs::create_dir_all(db_path).map_err(|e| format!("create {} failed: {}", db_path, e)));
+    let entries = try!(fs::read_dir(dir).map_err(|e| format!("read {} failed: {}", dir, e)));
+    let (mut linked, mut copied) = (0, 0);
+    for entry in entries {
+        let entry = try!(entry.map_err(|e| format!("read {} failed: {}", dir, e)));
+        let from = entry.path();
+        let to = Path::new(db_path).join(entry.file_name());
+        let sst = from.extension().map_or(false, |ext| ext == "sst");
+        if by == RestoreBy::Hardlink && sst && fs::hard_link(&from, &to).is_ok() {
+            linked += 1;
+            continue;
+        }
+        try!(fs::copy(&from, &to).map_err(|e| format!("copy {} failed: {}", from.display(), e)));
+        copied += 1;
+    }
+    let elapsed = timer.elapsed();
+    println!("restored {} from {} in {} ms, {} files linked, {} copied",
+             db_path,
+             dir,
+             elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000,
+             linked,
+             copied);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::MetadataExt;
+    use super::{RestoreBy, restore};
+
+    #[test]
+    fn test_restore() {
+        let root = env::temp_dir().join("rocksdb-in-tikv-checkpoint");
+        let _ = fs::remove_dir_all(&root);
+        let (dir, db) = (root.join("checkpoint"), root.join("db"));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["000007.sst", "MANIFEST-000009", "CURRENT"] {
+            File::create(dir.join(name)).unwrap().write_all(b"data").unwrap();
+        }
+
+        restore(&dir.to_string_lossy(), &db.to_string_lossy(), RestoreBy::Hardlink).unwrap();
+        assert_eq!(fs::metadata(db.join("000007.sst")).unwrap().nlink(), 2);
+        assert_eq!(fs::metadata(db.join("CURRENT")).unwrap().nlink(), 1);
+        assert!(restore(&dir.to_string_lossy(), &db.to_string_lossy(), RestoreBy::Copy).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
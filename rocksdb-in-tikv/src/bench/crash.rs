@@ -0,0 +1,372 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Crash recovery: a child process writes until it is killed by SIGKILL, then the db is
+// reopened with each WAL recovery mode, and every write the child acknowledged must be
+// there. Each mode runs with the writes synced or not. A killed process leaves its WAL
+// writes in the page cache, so no acknowledged write may be lost either way, sync only
+// matters on a host crash, which is not simulated here, and costs acknowledged writes.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use toml;
+use rand::{Rng, thread_rng};
+use rocksdb::{DB, WriteBatch, WriteOptions, Writable};
+
+use env::dbcfg;
+use plan::summary::Summary;
+
+// The subcommand the child runs.
+pub const WRITER_SUBCOMMAND: &'static str = "crash-writer";
+const ACK_PREFIX: &'static str = "ack ";
+
+pub struct CrashOpts {
+    // Each recovery mode runs in "<db_path>.mode-<mode>", with ".sync" appended when the
+    // writes are synced.
+    pub db_path: String,
+    pub configs: Vec<String>,
+    pub overrides: Vec<String>,
+    pub lenient: bool,
+    // The most keys the child writes.
+    pub count: usize,
+    pub key_len: usize,
+    pub val_len: usize,
+    pub batch_size: usize,
+    pub modes: Vec<i64>,
+    // Every mode runs with each of these as the sync of the writes.
+    pub syncs: Vec<bool>,
+    pub rounds: usize,
+    // The child is killed at random in this long after its first acknowledged write.
+    pub kill_within: Duration,
+}
+
+// Keys are the big endian index, padded to `key_len`, and values start with it, so the
+// verifier can regenerate any of them.
+fn crash_key(idx: usize, key_len: usize) -> Vec<u8> {
+    let mut key = vec![0; key_len];
+    for i in 0..8 {
+        if i < key_len {
+            key[i] = (idx >> (8 * (7 - i))) as u8;
+        }
+    }
+    key
+}
+
+fn crash_val(idx: usize, val_len: usize) -> Vec<u8> {
+    let mut val = crash_key(idx, val_len);
+    for (i, b) in val.iter_mut().enumerate().skip(8) {
+        *b = i as u8;
+    }
+    val
+}
+
+fn open_db(opts: &CrashOpts, db_path: &str, overrides: &[String]) -> Result<DB, String> {
+    let config = try!(dbcfg::load_config(&opts.configs, overrides));
+    let mut db_cfg = try!(dbcfg::get_db_config(&config, opts.lenient));
+    try!(db_cfg.create_info_log());
+    DB::open_cf(db_cfg.db_opts, db_path, &["default"], &[&db_cfg.cf_opts])
+}
+
+// Run in the child: write batches and print "ack <n>" after each one, `n` being the
+// number of keys written so far.
+pub fn write_until_killed(opts: &CrashOpts, sync: bool) -> Result<(), String> {
+    let db = try!(open_db(opts, &opts.db_path, &opts.overrides));
+    let mut writeopts = WriteOptions::new();
+    writeopts.set_sync(sync);
+    let stdout = io::stdout();
+    let mut written = 0;
+    while written < opts.count {
+        let wb = WriteBatch::new();
+        let end = (written + opts.batch_size).min(opts.count);
+        for idx in written..end {
+            try!(wb.put(&crash_key(idx, opts.key_len), &crash_val(idx, opts.val_len)));
+        }
+        try!(db.write_opt(wb, &writeopts));
+        written = end;
+        let mut out = stdout.lock();
+        try!(writeln!(out, "{}{}", ACK_PREFIX, written)
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("ack failed: {}", e)));
+    }
+    Ok(())
+}
+
+fn spawn_and_kill(opts: &CrashOpts,
+                  db_path: &str,
+                  mode: i64,
+                  sync: bool)
+                  -> Result<usize, String> {
+    let exe = try!(env::current_exe().map_err(|e| format!("locate the binary failed: {}", e)));
+    let mut cmd = Command::new(exe);
+    cmd.arg("-N")
+        .args(&["-d", db_path])
+        .args(&["-n", &opts.count.to_string()])
+        .args(&["-K", &opts.key_len.to_string()])
+        .args(&["-V", &opts.val_len.to_string()])
+        .args(&["-B", &opts.batch_size.to_string()]);
+    for c in &opts.configs {
+        cmd.args(&["-c", c]);
+    }
+    for s in &opts.overrides {
+        cmd.args(&["-s", s]);
+    }
+    cmd.args(&["-s", &format!("rocksdb.wal-recovery-mode={}", mode)]);
+    if opts.lenient {
+        cmd.arg("--lenient");
+    }
+    cmd.arg(WRITER_SUBCOMMAND);
+    if sync {
+        cmd.arg("--sync");
+    }
+    let mut child = try!(cmd.stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("spawn the writer failed: {}", e)));
+
+    let acked = Arc::new(AtomicUsize::new(0));
+    let stdout = child.stdout.take().unwrap();
+    let reader = {
+        let acked = acked.clone();
+        thread::spawn(move || for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(ref l) if l.starts_with(ACK_PREFIX) => {
+                    if let Ok(n) = l[ACK_PREFIX.len()..].parse() {
+                        acked.store(n, Ordering::SeqCst);
+                    }
+                }
+                Ok(l) => println!("{}", l),
+                Err(_) => break,
+            }
+        })
+    };
+
+    while acked.load(Ordering::SeqCst) == 0 {
+        if let Some(status) = try!(child.try_wait().map_err(|e| e.to_string())) {
+            let _ = reader.join();
+            return Err(format!("the writer exited before writing anything: {}", status));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let within = opts.kill_within.as_secs() * 1000 +
+                 opts.kill_within.subsec_nanos() as u64 / 1000000;
+    thread::sleep(Duration::from_millis(thread_rng().gen_range(0, within.max(1))));
+    // Sends SIGKILL, fails only if the writer finished already.
+    if child.kill().is_err() {
+        println!("warning: the writer finished all {} writes before the kill", opts.count);
+    }
+    try!(child.wait().map_err(|e| format!("wait for the writer failed: {}", e)));
+    let _ = reader.join();
+    Ok(acked.load(Ordering::SeqCst))
+}
+
+pub struct Round {
+    pub acked: usize,
+    // None if the db failed to open.
+    pub recovery: Option<Duration>,
+    pub lost: usize,
+    pub error: Option<String>,
+}
+
+pub struct ModeResult {
+    pub mode: i64,
+    pub sync: bool,
+    pub rounds: Vec<Round>,
+}
+
+impl ModeResult {
+    pub fn acked(&self) -> usize {
+        self.rounds.iter().map(|r| r.acked).sum()
+    }
+
+    pub fn lost(&self) -> usize {
+        self.rounds.iter().map(|r| r.lost).sum()
+    }
+
+    pub fn open_failures(&self) -> usize {
+        self.rounds.iter().filter(|r| r.recovery.is_none()).count()
+    }
+
+    pub fn recovery_ms(&self) -> Summary {
+        let ms: Vec<f64> = self.rounds
+            .iter()
+            .filter_map(|r| r.recovery)
+            .map(|d| d.as_secs() as f64 * 1e3 + d.subsec_nanos() as f64 / 1e6)
+            .collect();
+        Summary::of(&ms)
+    }
+
+    // Safe to use: every round reopened the db without losing an acknowledged write.
+    pub fn safe(&self) -> bool {
+        self.lost() == 0 && self.open_failures() == 0
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let rounds = self.rounds
+            .iter()
+            .map(|r| {
+                let mut t = BTreeMap::new();
+                t.insert("acked".to_owned(), toml::Value::Integer(r.acked as i64));
+                t.insert("lost".to_owned(), toml::Value::Integer(r.lost as i64));
+                if let Some(d) = r.recovery {
+                    let ms = d.as_secs() * 1000 + d.subsec_nanos() as u64 / 1000000;
+                    t.insert("recovery-ms".to_owned(), toml::Value::Integer(ms as i64));
+                }
+                if let Some(ref e) = r.error {
+                    t.insert("error".to_owned(), toml::Value::String(e.clone()));
+                }
+                toml::Value::Table(t)
+            })
+            .collect();
+        let mut table = BTreeMap::new();
+        table.insert("mode".to_owned(), toml::Value::Integer(self.mode));
+        table.insert("sync".to_owned(), toml::Value::Boolean(self.sync));
+        table.insert("acked".to_owned(), toml::Value::Integer(self.acked() as i64));
+        table.insert("safe".to_owned(), toml::Value::Boolean(self.safe()));
+        table.insert("lost".to_owned(), toml::Value::Integer(self.lost() as i64));
+        table.insert("open-failures".to_owned(),
+                     toml::Value::Integer(self.open_failures() as i64));
+        table.insert("recovery-ms".to_owned(), self.recovery_ms().to_toml());
+        table.insert("round".to_owned(), toml::Value::Array(rounds));
+        toml::Value::Table(table)
+    }
+}
+
+// Reopen the db the writer was killed on, and count the acknowledged writes missing.
+fn recover(opts: &CrashOpts, db_path: &str, mode: i64, acked: usize) -> Round {
+    let mut overrides = opts.overrides.clone();
+    overrides.push(format!("rocksdb.wal-recovery-mode={}", mode));
+    let timer = Instant::now();
+    let db = match open_db(opts, db_path, &overrides) {
+        Ok(db) => db,
+        Err(e) => {
+            return Round {
+                acked: acked,
+                recovery: None,
+                lost: 0,
+                error: Some(e),
+            }
+        }
+    };
+    let recovery = timer.elapsed();
+
+    let mut lost = 0;
+    let mut error = None;
+    for idx in 0..acked {
+        match db.get(&crash_key(idx, opts.key_len)) {
+            Ok(Some(ref v)) if **v == *crash_val(idx, opts.val_len) => {}
+            Ok(_) => lost += 1,
+            Err(e) => {
+                lost += acked - idx;
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    Round {
+        acked: acked,
+        recovery: Some(recovery),
+        lost: lost,
+        error: error,
+    }
+}
+
+pub fn run(opts: &CrashOpts) -> Result<Vec<ModeResult>, String> {
+    if opts.key_len < 8 || opts.val_len < 8 {
+        return Err("crash test needs key_len and val_len of 8 at least".to_owned());
+    }
+    let mut results = vec![];
+    for &mode in &opts.modes {
+        for &sync in &opts.syncs {
+            let db_path = format!("{}.mode-{}{}",
+                                  opts.db_path,
+                                  mode,
+                                  if sync { ".sync" } else { "" });
+            results.push(try!(run_mode(opts, &db_path, mode, sync)));
+        }
+    }
+    Ok(results)
+}
+
+fn run_mode(opts: &CrashOpts, db_path: &str, mode: i64, sync: bool) -> Result<ModeResult, String> {
+    let mut result = ModeResult {
+        mode: mode,
+        sync: sync,
+        rounds: vec![],
+    };
+    for round in 0..opts.rounds {
+        if Path::new(db_path).exists() {
+            try!(fs::remove_dir_all(db_path)
+                .map_err(|e| format!("remove {} failed: {}", db_path, e)));
+        }
+        let acked = try!(spawn_and_kill(opts, db_path, mode, sync));
+        let r = recover(opts, db_path, mode, acked);
+        println!("recovery mode {} sync {} round {}: {} acked, {} lost, recovered in {}{}",
+                 mode,
+                 sync,
+                 round,
+                 r.acked,
+                 r.lost,
+                 r.recovery.map_or("-".to_owned(), |d| {
+                     format!("{} ms", d.as_secs() * 1000 + d.subsec_nanos() as u64 / 1000000)
+                 }),
+                 r.error.as_ref().map_or(String::new(), |e| format!(", error: {}", e)));
+        result.rounds.push(r);
+    }
+    try!(fs::remove_dir_all(db_path).map_err(|e| format!("remove {} failed: {}", db_path, e)));
+    Ok(result)
+}
+
+pub fn print_results(results: &[ModeResult]) {
+    println!("{:<6} {:<6} {:>8} {:>12} {:>8} {:>14} {:>24} {:>6}",
+             "mode",
+             "sync",
+             "rounds",
+             "acked",
+             "lost",
+             "open-failures",
+             "recovery-ms",
+             "safe");
+    for r in results {
+        println!("{:<6} {:<6} {:>8} {:>12} {:>8} {:>14} {:>24} {:>6}",
+                 r.mode,
+                 r.sync,
+                 r.rounds.len(),
+                 r.acked(),
+                 r.lost(),
+                 r.open_failures(),
+                 r.recovery_ms().to_string(),
+                 r.safe());
+    }
+    println!("note: the writer is killed with SIGKILL, which only covers a process crash. \
+              Its unsynced writes are still in the page cache, so every mode may be safe \
+              here and still lose acknowledged writes on a host crash without sync.");
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crash_key, crash_val};
+
+    #[test]
+    fn test_crash_kv() {
+        assert_eq!(crash_key(0x0102, 10), vec![0, 0, 0, 0, 0, 0, 1, 2, 0, 0]);
+        assert_eq!(crash_val(1, 10), vec![0, 0, 0, 0, 0, 0, 0, 1, 8, 9]);
+        assert!(crash_key(1, 16) < crash_key(256, 16));
+    }
+}
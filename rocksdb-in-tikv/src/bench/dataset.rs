@@ -0,0 +1,251 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::collections::BTreeMap;
+use toml;
+
+use env::helper::{get_toml_int, get_toml_string, load_toml};
+use super::{BenchOpts, Workload};
+
+// Written into the db dir by the load phase, next to the RocksDB files.
+pub const DATASET_FILE: &'static str = "DATASET.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    // Fill an empty db and record what was written.
+    Load,
+    // Reuse a loaded db, the keys must be generated the same way as they were loaded.
+    Run,
+}
+
+impl Phase {
+    pub fn parse(s: &str) -> Result<Phase, String> {
+        match s {
+            "load" => Ok(Phase::Load),
+            "run" => Ok(Phase::Run),
+            _ => Err(format!("{} is not a valid phase, expect load or run", s)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Phase::Load => "load",
+            Phase::Run => "run",
+        }
+    }
+}
+
+// A write over the dataset: the first `count` keys put with values of `val_len`, or put
+// and deleted by `cf lock`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatasetWrite {
+    pub workload: String,
+    pub count: usize,
+    pub val_len: usize,
+}
+
+impl DatasetWrite {
+    pub fn of(opts: &BenchOpts) -> DatasetWrite {
+        DatasetWrite {
+            workload: opts.workload.name().to_owned(),
+            count: opts.count,
+            val_len: opts.val_len,
+        }
+    }
+
+    fn load(v: &toml::Value) -> Result<DatasetWrite, String> {
+        let get_int = |name| get_toml_int(v, name, None).map(|i| i as usize);
+        Ok(DatasetWrite {
+            workload: try!(get_toml_string(v, "workload", None)),
+            count: try!(get_int("count")),
+            val_len: try!(get_int("val-len")),
+        })
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("workload".to_owned(), toml::Value::String(self.workload.clone()));
+        t.insert("count".to_owned(), toml::Value::Integer(self.count as i64));
+        t.insert("val-len".to_owned(), toml::Value::Integer(self.val_len as i64));
+        toml::Value::Table(t)
+    }
+}
+
+// What the load phase wrote: `count` keys of `key_gen` with `seed`, and the writes of
+// the run phases after it, which generate their keys the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dataset {
+    pub workload: String,
+    pub count: usize,
+    pub key_len: usize,
+    pub val_len: usize,
+    pub key_gen: String,
+    pub seed: u32,
+    pub writes: Vec<DatasetWrite>,
+}
+
+impl Dataset {
+    pub fn of(opts: &BenchOpts) -> Dataset {
+        Dataset {
+            workload: opts.workload.name().to_owned(),
+            count: opts.count,
+            key_len: opts.key_len,
+            val_len: opts.val_len,
+            key_gen: opts.key_gen.clone(),
+            seed: opts.seed,
+            writes: vec![],
+        }
+    }
+
+    // Every write in order, the load first.
+    pub fn all_writes(&self) -> Vec<DatasetWrite> {
+        let load = DatasetWrite {
+            workload: self.workload.clone(),
+            count: self.count,
+            val_len: self.val_len,
+        };
+        let mut writes = vec![load];
+        writes.extend(self.writes.iter().cloned());
+        writes
+    }
+
+    // None if nothing was loaded into `db_path`.
+    pub fn load(db_path: &str) -> Result<Option<Dataset>, String> {
+        let path = Path::new(db_path).join(DATASET_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let path = path.to_string_lossy().into_owned();
+        let v = try!(load_toml(&path));
+        let get_int = |name| get_toml_int(&v, name, None).map(|i| i as usize);
+        let writes = match v.lookup("write") {
+            Some(&toml::Value::Array(ref items)) => {
+                try!(items.iter().map(DatasetWrite::load).collect::<Result<Vec<_>, _>>())
+            }
+            None => vec![],
+            Some(_) => return Err(format!("write of {} is not an array", path)),
+        };
+        let dataset = Dataset {
+            workload: try!(get_toml_string(&v, "workload", None)),
+            count: try!(get_int("count")),
+            key_len: try!(get_int("key-len")),
+            val_len: try!(get_int("val-len")),
+            key_gen: try!(get_toml_string(&v, "key-gen", None)),
+            seed: try!(get_int("seed")) as u32,
+            writes: writes,
+        };
+        Ok(Some(dataset))
+    }
+
+    pub fn save(&self, db_path: &str) -> Result<(), String> {
+        let path = Path::new(db_path).join(DATASET_FILE);
+        let mut f = try!(File::create(&path)
+            .map_err(|e| format!("create {} failed: {}", path.display(), e)));
+        f.write_all(self.to_toml().to_string().as_bytes())
+            .map_err(|e| format!("write {} failed: {}", path.display(), e))
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("workload".to_owned(), toml::Value::String(self.workload.clone()));
+        t.insert("count".to_owned(), toml::Value::Integer(self.count as i64));
+        t.insert("key-len".to_owned(), toml::Value::Integer(self.key_len as i64));
+        t.insert("val-len".to_owned(), toml::Value::Integer(self.val_len as i64));
+        t.insert("key-gen".to_owned(), toml::Value::String(self.key_gen.clone()));
+        t.insert("seed".to_owned(), toml::Value::Integer(self.seed as i64));
+        if !self.writes.is_empty() {
+            t.insert("write".to_owned(),
+                     toml::Value::Array(self.writes.iter().map(|w| w.to_toml()).collect()));
+        }
+        toml::Value::Table(t)
+    }
+
+    // Whether `opts` can run on this dataset: the keys must be generated the same way,
+    // and reads must not go past the loaded keys. Every mismatch is listed.
+    pub fn validate(&self, opts: &BenchOpts) -> Result<(), String> {
+        let mut errors = vec![];
+        if opts.key_gen != self.key_gen {
+            errors.push(format!("key_gen is {}, loaded with {}", opts.key_gen, self.key_gen));
+        }
+        if opts.key_len != self.key_len {
+            errors.push(format!("key_len is {}, loaded with {}", opts.key_len, self.key_len));
+        }
+        if opts.seed != self.seed {
+            errors.push(format!("seed is {}, loaded with {}", opts.seed, self.seed));
+        }
+        let reads = opts.workload == Workload::Read || opts.workload == Workload::Scan;
+        if reads && opts.count > self.count {
+            errors.push(format!("count is {}, only {} keys loaded", opts.count, self.count));
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(format!("dataset mismatch:\n  {}", errors.join("\n  ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use super::{Dataset, DatasetWrite};
+    use super::super::{BenchOpts, Workload};
+
+    #[test]
+    fn test_dataset() {
+        let mut opts = BenchOpts {
+            db_path: env::temp_dir().join("rocksdb-in-tikv-dataset").to_string_lossy().into_owned(),
+            configs: vec![],
+            overrides: vec![],
+            count: 100,
+            key_len: 32,
+            val_len: 128,
+            batch_size: 16,
+            key_gen: "random".to_owned(),
+            seed: 3,
+            workload: Workload::CfDefault,
+            phase: None,
+            checkpoint: None,
+            settle: None,
+            stats_interval: None,
+            lenient: false,
+        };
+        fs::create_dir_all(&opts.db_path).unwrap();
+        let mut dataset = Dataset::of(&opts);
+        dataset.save(&opts.db_path).unwrap();
+        assert_eq!(Dataset::load(&opts.db_path).unwrap(), Some(dataset.clone()));
+        opts.workload = Workload::CfLock;
+        opts.count = 200;
+        dataset.writes.push(DatasetWrite::of(&opts));
+        dataset.save(&opts.db_path).unwrap();
+        assert_eq!(Dataset::load(&opts.db_path).unwrap(), Some(dataset.clone()));
+        let writes = dataset.all_writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!((writes[0].workload.as_str(), writes[0].count), ("cf default", 100));
+        assert_eq!((writes[1].workload.as_str(), writes[1].count), ("cf lock", 200));
+        opts.count = 100;
+        fs::remove_dir_all(&opts.db_path).unwrap();
+
+        opts.workload = Workload::Read;
+        opts.val_len = 64;
+        assert!(dataset.validate(&opts).is_ok());
+        opts.count = 101;
+        opts.seed = 4;
+        let err = dataset.validate(&opts).unwrap_err();
+        assert!(err.contains("seed is 4, loaded with 3"));
+        assert!(err.contains("only 100 keys loaded"));
+    }
+}
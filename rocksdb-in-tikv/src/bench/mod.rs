@@ -0,0 +1,381 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod dataset;
+pub mod checkpoint;
+pub mod settle;
+pub mod crash;
+pub mod verify;
+
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use toml;
+use rocksdb::DB;
+
+use env::{dbcfg, host, options};
+use env::meta::Metadata;
+use sim::key::{KeyGen, RepeatKeyGen, IncreaseKeyGen, RandomKeyGen};
+use sim::val::ConstValGen;
+use sim::cf::{cf_default_w, cf_lock_w, cf_write_w, cf_raft_w};
+use sim::read::{point_r, scan_r};
+use stats::{self, Report, Sampler, Samples};
+use stats::statistics::Statistics;
+use stats::amp::Amplification;
+use stats::cache::CacheUsage;
+use stats::disk::DiskUsage;
+use self::dataset::{Dataset, DatasetWrite, Phase};
+use self::settle::{Settle, SettleResult};
+
+const ROCKSDB_DB_STATS_KEY: &'static str = "rocksdb.dbstats";
+const ROCKSDB_CF_STATS_KEY: &'static str = "rocksdb.cfstats";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Workload {
+    CfDefault,
+    CfLock,
+    CfWrite,
+    CfRaft,
+    Txn,
+    Read,
+    Scan,
+}
+
+impl Workload {
+    // Parse workloads the way they are written on the command line, eg: "cf default".
+    pub fn parse(s: &str) -> Result<Workload, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match &*words.join(" ") {
+            "cf default" => Ok(Workload::CfDefault),
+            "cf lock" => Ok(Workload::CfLock),
+            "cf write" => Ok(Workload::CfWrite),
+            "cf raft" => Ok(Workload::CfRaft),
+            "txn" => Ok(Workload::Txn),
+            "read" => Ok(Workload::Read),
+            "scan" => Ok(Workload::Scan),
+            _ => Err(format!("{} is not a valid workload", s)),
+        }
+    }
+
+    // Whether it writes keys a later verify can regenerate.
+    pub fn writes(&self) -> bool {
+        match *self {
+            Workload::CfDefault | Workload::CfLock | Workload::CfWrite | Workload::CfRaft => true,
+            Workload::Txn | Workload::Read | Workload::Scan => false,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Workload::CfDefault => "cf default",
+            Workload::CfLock => "cf lock",
+            Workload::CfWrite => "cf write",
+            Workload::CfRaft => "cf raft",
+            Workload::Txn => "txn",
+            Workload::Read => "read",
+            Workload::Scan => "scan",
+        }
+    }
+}
+
+pub struct BenchOpts {
+    pub db_path: String,
+    // Config files merged in order, then the "key=value" overrides on top.
+    pub configs: Vec<String>,
+    pub overrides: Vec<String>,
+    pub count: usize,
+    pub key_len: usize,
+    pub val_len: usize,
+    pub batch_size: usize,
+    pub key_gen: String,
+    pub seed: u32,
+    pub workload: Workload,
+    // None to write into the db without recording a dataset.
+    pub phase: Option<Phase>,
+    // Take a checkpoint of the db into this dir after the run.
+    pub checkpoint: Option<String>,
+    // Let the db settle before the workload runs.
+    pub settle: Option<Settle>,
+    pub stats_interval: Option<Duration>,
+    // Warn about unknown config keys instead of failing.
+    pub lenient: bool,
+}
+
+pub struct BenchResult {
+    pub workload: Workload,
+    pub count: usize,
+    pub elapsed: Duration,
+    pub statistics: Statistics,
+    pub amp: Amplification,
+    pub samples: Samples,
+    pub cache: CacheUsage,
+    pub disk: DiskUsage,
+    pub meta: Metadata,
+    // The dataset loaded or reused by the run.
+    pub dataset: Option<Dataset>,
+    pub settle: Option<SettleResult>,
+    pub rocksdb_version: Option<String>,
+    // The resolved config the run used.
+    pub config: toml::Value,
+}
+
+impl BenchOpts {
+    // What the run does, leaving out where and how it reports. The config files are
+    // left out too, the resolved config covers them.
+    pub fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("workload".to_owned(),
+                 toml::Value::String(self.workload.name().to_owned()));
+        t.insert("count".to_owned(), toml::Value::Integer(self.count as i64));
+        t.insert("key-len".to_owned(), toml::Value::Integer(self.key_len as i64));
+        t.insert("val-len".to_owned(), toml::Value::Integer(self.val_len as i64));
+        t.insert("batch-size".to_owned(), toml::Value::Integer(self.batch_size as i64));
+        t.insert("key-gen".to_owned(), toml::Value::String(self.key_gen.clone()));
+        t.insert("seed".to_owned(), toml::Value::Integer(self.seed as i64));
+        if let Some(phase) = self.phase {
+            t.insert("phase".to_owned(), toml::Value::String(phase.name().to_owned()));
+        }
+        if let Some(ref settle) = self.settle {
+            t.insert("settle".to_owned(), settle.to_toml());
+        }
+        toml::Value::Table(t)
+    }
+}
+
+impl BenchResult {
+    pub fn elapsed_ms(&self) -> u64 {
+        self.elapsed.as_secs() * 1000 + (self.elapsed.subsec_nanos() as f64 / 1e6) as u64
+    }
+
+    pub fn tps(&self) -> f64 {
+        self.count as f64 /
+        (self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 / 1e9)
+    }
+
+    // The p99 latency of the operations the workload runs.
+    pub fn p99(&self) -> Option<f64> {
+        let histogram = match self.workload {
+            Workload::Read => "db-get-micros",
+            Workload::Scan => "db-seek-micros",
+            _ => "db-write-micros",
+        };
+        self.statistics.histogram(histogram).map(|h| h.percentile99)
+    }
+
+    pub fn to_report(&self) -> Report {
+        let mut bench = BTreeMap::new();
+        bench.insert("count".to_owned(), toml::Value::Integer(self.count as i64));
+        bench.insert("elapsed-ms".to_owned(),
+                     toml::Value::Integer(self.elapsed_ms() as i64));
+        bench.insert("tps".to_owned(), toml::Value::Float(self.tps()));
+        if let Some(ref v) = self.rocksdb_version {
+            bench.insert("rocksdb-version".to_owned(), toml::Value::String(v.clone()));
+        }
+
+        let mut report = Report::new();
+        report.insert("meta", self.meta.to_toml());
+        report.insert("bench", toml::Value::Table(bench));
+        report.insert("config", self.config.clone());
+        if let Some(ref dataset) = self.dataset {
+            report.insert("dataset", dataset.to_toml());
+        }
+        if let Some(ref settle) = self.settle {
+            report.insert("settle", settle.to_toml());
+        }
+        report.insert("statistics", self.statistics.to_toml());
+        report.insert("amplification", self.amp.to_toml());
+        report.insert("stalls", self.samples.stalls.to_toml());
+        report.insert("os", self.samples.os.to_toml());
+        report.insert("block-cache", self.cache.to_toml());
+        report.insert("disk-usage", self.disk.to_toml());
+        if !self.samples.intervals.is_empty() {
+            report.insert("intervals", stats::intervals_to_toml(&self.samples));
+        }
+        report
+    }
+}
+
+fn new_key_gen(name: &str,
+               key_len: usize,
+               count: usize,
+               seed: u32)
+               -> Result<Box<KeyGen>, String> {
+    match name {
+        "repeat" => Ok(Box::new(RepeatKeyGen::new(key_len, count, seed))),
+        "increase" => Ok(Box::new(IncreaseKeyGen::new(key_len, count))),
+        "random" => Ok(Box::new(RandomKeyGen::new(key_len, count, seed))),
+        invalid => Err(format!("{} is not a valid key_gen", invalid)),
+    }
+}
+
+// The dataset to load, or the one recorded by a former load phase that this run reuses.
+fn prepare_dataset(opts: &BenchOpts) -> Result<Option<Dataset>, String> {
+    match opts.phase {
+        None => Ok(None),
+        Some(Phase::Load) => {
+            if opts.workload == Workload::Read || opts.workload == Workload::Scan {
+                return Err(format!("{} workload can not load a dataset", opts.workload.name()));
+            }
+            if Path::new(&opts.db_path).join("CURRENT").exists() {
+                return Err(format!("{} already has a db, load into a new one", opts.db_path));
+            }
+            Ok(Some(Dataset::of(opts)))
+        }
+        Some(Phase::Run) => {
+            match try!(Dataset::load(&opts.db_path)) {
+                Some(dataset) => {
+                    try!(dataset.validate(opts));
+                    Ok(Some(dataset))
+                }
+                None => {
+                    Err(format!("no dataset in {}, please run the load phase first",
+                                opts.db_path))
+                }
+            }
+        }
+    }
+}
+
+fn output_stats(db: &DB) {
+    if let Some(db_stats) = db.get_property_value(ROCKSDB_DB_STATS_KEY) {
+        print!("{}", db_stats);
+    }
+    for name in db.cf_names() {
+        let handler = db.cf_handle(name).expect("");
+        if let Some(cf_stats) = db.get_property_value_cf(handler, ROCKSDB_CF_STATS_KEY) {
+            print!("{}", cf_stats);
+        }
+    }
+}
+
+// Print the resolved config, and if `db_path` is given, the OPTIONS file RocksDB wrote
+// there the last time the db was opened. Nothing is opened or created.
+pub fn print_config(configs: &[String],
+                    overrides: &[String],
+                    lenient: bool,
+                    db_path: Option<&str>)
+                    -> Result<(), String> {
+    let config = try!(dbcfg::load_config(configs, overrides));
+    let db_cfg = try!(dbcfg::get_db_config(&config, lenient));
+    match db_path {
+        Some(path) => dbcfg::print_db_config(&db_cfg.resolved, path),
+        None => {
+            println!("resolved config:");
+            print!("{}", db_cfg.resolved);
+        }
+    }
+    Ok(())
+}
+
+pub fn run(opts: &BenchOpts) -> Result<BenchResult, String> {
+    let mut timer = Instant::now();
+    if opts.workload == Workload::Txn {
+        return Err("txn bench mark not impl".to_owned());
+    }
+
+    let mut dataset = try!(prepare_dataset(opts));
+    let config = try!(dbcfg::load_config(&opts.configs, &opts.overrides));
+    let mut db_cfg = try!(dbcfg::get_db_config(&config, opts.lenient));
+    try!(db_cfg.create_info_log());
+    let db = try!(DB::open_cf(db_cfg.db_opts, &opts.db_path, &["default"], &[&db_cfg.cf_opts]));
+    let db = Arc::new(db);
+    dbcfg::print_db_config(&db_cfg.resolved, &opts.db_path);
+
+    let mut key_gen = try!(new_key_gen(&opts.key_gen, opts.key_len, opts.count, opts.seed));
+    let mut val_gen = ConstValGen::new(opts.val_len, opts.seed);
+
+    // With a settle the elapsed time starts after it, its wait is in the settle report.
+    // Without one it still covers opening the db, as before settles were added.
+    let settled = match opts.settle {
+        Some(ref s) => {
+            let settled = try!(settle::settle(&db, s));
+            timer = Instant::now();
+            Some(settled)
+        }
+        None => None,
+    };
+
+    let disk = host::mount_of(&opts.db_path).and_then(|m| host::block_device(&m.device));
+    let sampler = Sampler::start(db.clone(), opts.stats_interval, disk);
+    let res = match opts.workload {
+        Workload::CfDefault => cf_default_w(&db, &mut *key_gen, &mut val_gen, opts.batch_size),
+        Workload::CfLock => cf_lock_w(&db, &mut *key_gen, &mut val_gen, opts.batch_size),
+        Workload::CfWrite => cf_write_w(&db, &mut *key_gen, &mut val_gen, opts.batch_size),
+        Workload::CfRaft => cf_raft_w(&db, &mut *key_gen, &mut val_gen, opts.batch_size),
+        Workload::Read => point_r(&db, &mut *key_gen),
+        Workload::Scan => scan_r(&db, &mut *key_gen, opts.batch_size, db_cfg.prefix_extractor),
+        Workload::Txn => unreachable!(),
+    };
+    let samples = try!(sampler.stop());
+    // Reading the stats and the host, walking the db dirs and saving the dataset are not
+    // measured.
+    let elapsed = timer.elapsed();
+    let meta = Metadata::collect(&opts.db_path);
+    print!("{}", meta);
+
+    output_stats(&db);
+    let statistics = Statistics::collect(&db);
+    print!("{}", statistics);
+    let amp = Amplification::collect(&db, &statistics, (opts.key_len + opts.val_len) as u64);
+    print!("{}", amp);
+    print!("{}", samples.stalls);
+    let cache = CacheUsage::collect(&db, db_cfg.shared_cache.is_some());
+    print!("{}", cache);
+    let resolved = &db_cfg.resolved;
+    let resolved_dir = |key| resolved.lookup(key).and_then(|v| v.as_str()).unwrap_or("").to_owned();
+    let disk_usage = DiskUsage::collect(&db,
+                                        &opts.db_path,
+                                        &resolved_dir("rocksdb.wal-dir"),
+                                        &resolved_dir("rocksdb.info-log-dir"));
+    print!("{}", disk_usage);
+
+    try!(res);
+    match opts.phase {
+        Some(Phase::Load) => try!(dataset.as_ref().unwrap().save(&opts.db_path)),
+        // Verify checks every write over the loaded keys, not only the load.
+        Some(Phase::Run) if opts.workload.writes() => {
+            let dataset = dataset.as_mut().unwrap();
+            dataset.writes.push(DatasetWrite::of(opts));
+            try!(dataset.save(&opts.db_path));
+        }
+        _ => {}
+    }
+    // Creating a checkpoint is not measured either, it prints its own time.
+    if let Some(ref dir) = opts.checkpoint {
+        try!(checkpoint::create(&db, &opts.db_path, dir));
+    }
+
+    let result = BenchResult {
+        workload: opts.workload,
+        count: opts.count,
+        elapsed: elapsed,
+        statistics: statistics,
+        amp: amp,
+        samples: samples,
+        cache: cache,
+        disk: disk_usage,
+        meta: meta,
+        dataset: dataset,
+        settle: settled,
+        rocksdb_version: options::rocksdb_version(&opts.db_path),
+        config: db_cfg.resolved,
+    };
+    println!("invoke {} times in {} ms, tps: {}",
+             result.count,
+             result.elapsed_ms(),
+             result.tps() as u64);
+    print!("{}", result.samples.os);
+    Ok(result)
+}
@@ -0,0 +1,175 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant};
+use toml;
+use rocksdb::DB;
+
+const ROCKSDB_COMPACTION_PENDING: &'static str = "rocksdb.compaction-pending";
+const ROCKSDB_NUM_RUNNING_COMPACTIONS: &'static str = "rocksdb.num-running-compactions";
+const ROCKSDB_NUM_RUNNING_FLUSHES: &'static str = "rocksdb.num-running-flushes";
+const ROCKSDB_PENDING_COMPACTION_BYTES: &'static str = "rocksdb.estimate-pending-compaction-bytes";
+
+const POLL_INTERVAL_MS: u64 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettleMode {
+    // Only sleep.
+    Sleep,
+    // Flush, then wait for the background compactions to finish.
+    Wait,
+    // Flush, then compact every cf fully.
+    Compact,
+}
+
+impl SettleMode {
+    pub fn parse(s: &str) -> Result<SettleMode, String> {
+        match s {
+            "sleep" => Ok(SettleMode::Sleep),
+            "wait" => Ok(SettleMode::Wait),
+            "compact" => Ok(SettleMode::Compact),
+            _ => Err(format!("{} is not a valid settle mode, expect sleep, wait or compact", s)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SettleMode::Sleep => "sleep",
+            SettleMode::Wait => "wait",
+            SettleMode::Compact => "compact",
+        }
+    }
+}
+
+// How to let the db settle before the workload runs.
+#[derive(Clone, Copy, Debug)]
+pub struct Settle {
+    pub mode: SettleMode,
+    // Wait until the estimated pending compaction bytes are at most this.
+    pub max_pending_bytes: u64,
+    // Give up waiting after this long, and go on with a warning.
+    pub timeout: Duration,
+    // Sleep after the db settled.
+    pub sleep: Duration,
+}
+
+impl Settle {
+    pub fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("mode".to_owned(), toml::Value::String(self.mode.name().to_owned()));
+        t.insert("max-pending-bytes".to_owned(),
+                 toml::Value::Integer(self.max_pending_bytes as i64));
+        t.insert("timeout-ms".to_owned(), toml::Value::Integer(millis(self.timeout) as i64));
+        t.insert("sleep-ms".to_owned(), toml::Value::Integer(millis(self.sleep) as i64));
+        toml::Value::Table(t)
+    }
+}
+
+pub struct SettleResult {
+    pub mode: SettleMode,
+    pub wait: Duration,
+    pub sleep: Duration,
+    pub pending_bytes: u64,
+    pub timed_out: bool,
+}
+
+impl SettleResult {
+    pub fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("mode".to_owned(), toml::Value::String(self.mode.name().to_owned()));
+        t.insert("wait-ms".to_owned(), toml::Value::Integer(millis(self.wait) as i64));
+        t.insert("sleep-ms".to_owned(), toml::Value::Integer(millis(self.sleep) as i64));
+        t.insert("pending-bytes".to_owned(),
+                 toml::Value::Integer(self.pending_bytes as i64));
+        t.insert("timed-out".to_owned(), toml::Value::Boolean(self.timed_out));
+        toml::Value::Table(t)
+    }
+}
+
+fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + d.subsec_nanos() as u64 / 1000000
+}
+
+// Summed over every cf: whether a compaction is pending, the running compactions and
+// flushes, and the estimated pending compaction bytes.
+fn background_work(db: &DB) -> (u64, u64, u64) {
+    let (mut busy, mut running, mut pending_bytes) = (0, 0, 0);
+    for name in db.cf_names() {
+        let handle = db.cf_handle(name).expect("");
+        let get = |key| db.get_property_int_cf(handle, key).unwrap_or(0);
+        busy += get(ROCKSDB_COMPACTION_PENDING);
+        running += get(ROCKSDB_NUM_RUNNING_COMPACTIONS) + get(ROCKSDB_NUM_RUNNING_FLUSHES);
+        pending_bytes += get(ROCKSDB_PENDING_COMPACTION_BYTES);
+    }
+    (busy, running, pending_bytes)
+}
+
+fn flush(db: &DB) -> Result<(), String> {
+    for name in db.cf_names() {
+        let handle = db.cf_handle(name).expect("");
+        try!(db.flush_cf(handle, true).map_err(|e| format!("flush cf {} failed: {}", name, e)));
+    }
+    Ok(())
+}
+
+pub fn settle(db: &DB, opts: &Settle) -> Result<SettleResult, String> {
+    let timer = Instant::now();
+    let mut timed_out = false;
+    match opts.mode {
+        SettleMode::Sleep => {}
+        SettleMode::Wait => {
+            try!(flush(db));
+            loop {
+                let (busy, running, pending_bytes) = background_work(db);
+                if busy == 0 && running == 0 && pending_bytes <= opts.max_pending_bytes {
+                    break;
+                }
+                if timer.elapsed() >= opts.timeout {
+                    println!("warning: db not settled in {} s, compaction pending: {}, \
+                              running: {}, pending bytes: {}",
+                             opts.timeout.as_secs(),
+                             busy,
+                             running,
+                             pending_bytes);
+                    timed_out = true;
+                    break;
+                }
+                thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            }
+        }
+        SettleMode::Compact => {
+            try!(flush(db));
+            for name in db.cf_names() {
+                db.compact_range_cf(db.cf_handle(name).expect(""), None, None);
+            }
+        }
+    }
+    let wait = timer.elapsed();
+    thread::sleep(opts.sleep);
+
+    let result = SettleResult {
+        mode: opts.mode,
+        wait: wait,
+        sleep: opts.sleep,
+        pending_bytes: background_work(db).2,
+        timed_out: timed_out,
+    };
+    println!("settled by {} in {} ms, then slept {} ms, pending compaction bytes: {}",
+             result.mode.name(),
+             millis(result.wait),
+             millis(result.sleep),
+             result.pending_bytes);
+    Ok(result)
+}
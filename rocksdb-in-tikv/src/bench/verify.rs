@@ -0,0 +1,201 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+use rocksdb::{DB, ReadOptions, SeekKey};
+
+use env::dbcfg;
+use sim::val::{ValGen, ConstValGen};
+use super::{BenchOpts, Workload, new_key_gen};
+use super::dataset::Dataset;
+
+// Keys printed at most per kind of mismatch.
+const MAX_SAMPLES: usize = 10;
+
+fn hex(key: &[u8]) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Default)]
+pub struct VerifyResult {
+    // Keys regenerated and point got.
+    pub checked: usize,
+    // Written keys not found.
+    pub missing: usize,
+    // Written keys found with another value.
+    pub mismatched: usize,
+    // Deleted keys found.
+    pub unexpected: usize,
+    // Entries seen by a full scan, and the entries expected.
+    pub scanned: usize,
+    pub expected_entries: usize,
+    // Entries seen by the scan with another value.
+    pub scan_mismatched: usize,
+    pub samples: Vec<String>,
+}
+
+impl VerifyResult {
+    pub fn ok(&self) -> bool {
+        self.missing == 0 && self.mismatched == 0 && self.unexpected == 0 &&
+        self.scan_mismatched == 0 && self.scanned == self.expected_entries
+    }
+
+    fn sample(&mut self, kind: &str, key: &[u8]) {
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(format!("{} {}", kind, hex(key)));
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("ok".to_owned(), toml::Value::Boolean(self.ok()));
+        for &(name, n) in &[("checked", self.checked),
+                            ("missing", self.missing),
+                            ("mismatched", self.mismatched),
+                            ("unexpected", self.unexpected),
+                            ("scanned", self.scanned),
+                            ("expected-entries", self.expected_entries),
+                            ("scan-mismatched", self.scan_mismatched)] {
+            t.insert(name.to_owned(), toml::Value::Integer(n as i64));
+        }
+        t.insert("samples".to_owned(),
+                 toml::Value::Array(self.samples
+                     .iter()
+                     .map(|s| toml::Value::String(s.clone()))
+                     .collect()));
+        toml::Value::Table(t)
+    }
+}
+
+impl fmt::Display for VerifyResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f,
+                      "verify {}: {} keys checked, {} missing, {} mismatched, {} unexpected, \
+                       scanned {} entries of {} expected, {} mismatched",
+                      if self.ok() { "ok" } else { "FAILED" },
+                      self.checked,
+                      self.missing,
+                      self.mismatched,
+                      self.unexpected,
+                      self.scanned,
+                      self.expected_entries,
+                      self.scan_mismatched));
+        for s in &self.samples {
+            try!(writeln!(f, "  {}", s));
+        }
+        Ok(())
+    }
+}
+
+// Regenerate the keys and the values written into the db and check them: `cf lock`
+// deletes every key it puts, the other writes keep them. A dataset recorded by the load
+// phase is verified instead of the one described by `opts`, with the writes of the run
+// phases after it: a key is left as the last write covering it wrote it.
+//
+// The scan expects one entry per generated key, a single one with the repeat key_gen,
+// so it assumes random keys do not collide, which holds for keys of 8 bytes or more.
+pub fn verify(opts: &BenchOpts) -> Result<VerifyResult, String> {
+    let dataset = match try!(Dataset::load(&opts.db_path)) {
+        Some(dataset) => dataset,
+        None => Dataset::of(opts),
+    };
+    let writes = dataset.all_writes();
+    // The value each write leaves, None if it deletes the keys.
+    let mut vals = vec![];
+    for w in &writes {
+        let val = match try!(Workload::parse(&w.workload)) {
+            Workload::CfLock => None,
+            Workload::CfDefault | Workload::CfWrite | Workload::CfRaft => {
+                Some(ConstValGen::new(w.val_len, dataset.seed).next().unwrap().to_vec())
+            }
+            wl => return Err(format!("{} workload writes nothing to verify", wl.name())),
+        };
+        vals.push(val);
+    }
+    // Every index is the same key with the repeat key_gen, the last write has it then.
+    let repeat = dataset.key_gen == "repeat";
+    let last_write = |idx: usize| {
+        let idx = if repeat { 0 } else { idx };
+        writes.iter().rposition(|w| w.count > idx).unwrap()
+    };
+    let count = writes.iter().map(|w| w.count).max().unwrap();
+
+    let config = try!(dbcfg::load_config(&opts.configs, &opts.overrides));
+    let mut db_cfg = try!(dbcfg::get_db_config(&config, opts.lenient));
+    try!(db_cfg.create_info_log());
+    let db = try!(DB::open_cf(db_cfg.db_opts, &opts.db_path, &["default"], &[&db_cfg.cf_opts]));
+
+    let mut keys = try!(new_key_gen(&dataset.key_gen, dataset.key_len, count, dataset.seed));
+    let mut result = VerifyResult::default();
+    while let Some(key) = keys.next() {
+        let expected = &vals[last_write(result.checked)];
+        if expected.is_some() && (!repeat || result.checked == 0) {
+            result.expected_entries += 1;
+        }
+        result.checked += 1;
+        match (try!(db.get(key)), expected) {
+            (Some(_), &None) => {
+                result.unexpected += 1;
+                result.sample("unexpected", key);
+            }
+            (Some(ref v), &Some(ref val)) if **v != **val => {
+                result.mismatched += 1;
+                result.sample("mismatched", key);
+            }
+            (None, &Some(_)) => {
+                result.missing += 1;
+                result.sample("missing", key);
+            }
+            _ => {}
+        }
+    }
+
+    let mut readopts = ReadOptions::new();
+    readopts.set_total_order_seek(true);
+    let mut iter = db.iter_opt(readopts);
+    let mut valid = iter.seek(SeekKey::Start);
+    while valid {
+        result.scanned += 1;
+        let value = iter.value();
+        if !vals.iter().any(|v| v.as_ref().map_or(false, |v| value == &**v)) {
+            result.scan_mismatched += 1;
+            result.sample("scan-mismatched", iter.key());
+        }
+        valid = iter.next();
+    }
+    print!("{}", result);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MAX_SAMPLES, VerifyResult};
+
+    #[test]
+    fn test_verify_result() {
+        let mut result = VerifyResult::default();
+        result.scanned = 2;
+        result.expected_entries = 2;
+        assert!(result.ok());
+
+        for i in 0..MAX_SAMPLES + 1 {
+            result.missing += 1;
+            result.sample("missing", &[i as u8, 0xab]);
+        }
+        assert!(!result.ok());
+        assert_eq!(result.samples.len(), MAX_SAMPLES);
+        assert_eq!(result.samples[1], "missing 01ab");
+    }
+}
@@ -12,9 +12,86 @@
 // limitations under the License.
 
 use std::env;
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
 
+use super::host;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Status, String> {
+        match s {
+            "pass" => Ok(Status::Pass),
+            "warn" => Ok(Status::Warn),
+            "fail" => Ok(Status::Fail),
+            _ => Err(format!("{} is not a valid check status, [pass, warn, fail]", s)),
+        }
+    }
+}
+
+// Result of one check, with what was observed and what is expected.
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: Status,
+    pub observed: String,
+    pub expect: String,
+}
+
+impl CheckResult {
+    pub fn new<S: Into<String>>(name: &str,
+                                status: Status,
+                                observed: S,
+                                expect: &str)
+                                -> CheckResult {
+        CheckResult {
+            name: name.to_owned(),
+            status: status,
+            observed: observed.into(),
+            expect: expect.to_owned(),
+        }
+    }
+
+    // `bad` if not `ok`.
+    fn expect<S: Into<String>>(name: &str,
+                               ok: bool,
+                               bad: Status,
+                               observed: S,
+                               expect: &str)
+                               -> CheckResult {
+        CheckResult::new(name, if ok { Status::Pass } else { bad }, observed, expect)
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} {}: got {}, expect {}",
+               self.status.name(),
+               self.name,
+               self.observed,
+               self.expect)
+    }
+}
+
+// The open files' limit `getrlimit` returned, raised to `expect` if it is lower.
 #[cfg(unix)]
-pub fn check_max_open_fds(expect: u64) -> Result<(), String> {
+pub fn check_max_open_fds(expect: u64) -> Result<u64, String> {
     use std::mem;
     use libc;
 
@@ -25,7 +102,7 @@ pub fn check_max_open_fds(expect: u64) -> Result<(), String> {
             return Err("check_max_open_fds failed".to_owned());
         }
         if fd_limit.rlim_cur >= expect {
-            return Ok(());
+            return Ok(fd_limit.rlim_cur);
         }
 
         let prev_limit = fd_limit.rlim_cur;
@@ -36,7 +113,7 @@ pub fn check_max_open_fds(expect: u64) -> Result<(), String> {
         }
         err = libc::setrlimit(libc::RLIMIT_NOFILE, &fd_limit);
         if err == 0 {
-            return Ok(());
+            return Ok(prev_limit);
         }
         Err(format!("open files' limit is too small, got {}, expect >= {}",
             prev_limit, expect))
@@ -44,31 +121,23 @@ pub fn check_max_open_fds(expect: u64) -> Result<(), String> {
 }
 
 #[cfg(not(unix))]
-pub fn check_max_open_fds(_: u64) -> Result<(), String> {
-    Ok(())
+pub fn check_max_open_fds(_: u64) -> Result<u64, String> {
+    Ok(u64::max_value())
 }
 
 #[cfg(target_os = "linux")]
 mod check_kernel {
     use std::fs;
-    use std::io::Read;
+    use super::{CheckResult, Status};
+    use super::super::host;
 
-    type Checker = Fn(i64, i64) -> bool;
-
-    fn check_kernel_params(param_path: &str,
-        expect: i64, checker: Box<Checker>) -> Result<(), String> {
-
-        let mut buffer = String::new();
-        if let Err(e) = fs::File::open(param_path).and_then(|mut f| f.read_to_string(&mut buffer)) {
-            return Err(format!("open path failed while checking kernel params: {}, {}", param_path, e));
-        }
-
-        let got = buffer.trim_matches('\n').parse::<i64>();
-        if let Err(e) = got {
-            return Err(format!("pasrse params failed while checking kernel params: {}, {}", param_path, e));
-        }
-        let got = got.unwrap();
+    type Checker = Fn(i64) -> bool;
 
+    fn check_kernel_param(param_path: &str,
+                          expect: &str,
+                          bad: Status,
+                          checker: Box<Checker>)
+                          -> CheckResult {
         let mut param = String::new();
         // skip 3, ["", "proc", "sys", ...]
         for path in param_path.split('/').skip(3) {
@@ -77,52 +146,243 @@ mod check_kernel {
         }
         param.pop();
 
-        if !checker(got, expect) {
-            return Err(format!("kernel parameters {} got {}, expect {}", param, got, expect));
+        let got = match host::read_trimmed(param_path) {
+            Some(got) => got,
+            None => return CheckResult::new(&param, Status::Fail, "unreadable", expect),
+        };
+        match got.parse::<i64>() {
+            Ok(v) => CheckResult::expect(&param, checker(v), bad, got, expect),
+            Err(_) => CheckResult::new(&param, Status::Fail, got, expect),
         }
-
-        Ok(())
     }
 
-    pub fn check_kernel() -> Vec<Result<(), String>> {
-        let params: Vec<(&str, i64, Box<Checker>)> = vec![
-            // Check vm.swappiness.
-            ("/proc/sys/vm/swappiness", 0, Box::new(|got, expect| got == expect)),
+    pub fn check_kernel() -> Vec<CheckResult> {
+        let params: Vec<(&str, &str, Status, Box<Checker>)> = vec![
+            ("/proc/sys/vm/swappiness", "0", Status::Fail, Box::new(|got| got == 0)),
+            // Large dirty page caches get flushed in bursts, which show up as write stalls.
+            ("/proc/sys/vm/dirty_ratio", "<= 40", Status::Warn, Box::new(|got| got <= 40)),
+            ("/proc/sys/vm/dirty_background_ratio",
+             "<= 10",
+             Status::Warn,
+             Box::new(|got| got <= 10)),
         ];
+        params.into_iter()
+            .map(|(path, expect, bad, checker)| check_kernel_param(path, expect, bad, checker))
+            .collect()
+    }
 
-        let mut errors = Vec::with_capacity(params.len());
-        for (param_path, expect, checker) in params {
-            if let Err(e) = check_kernel_params(param_path, expect, checker) {
-                errors.push(Err(e));
+    pub fn check_transparent_hugepage() -> CheckResult {
+        let name = "transparent-hugepage";
+        let expect = "never";
+        match host::read_trimmed("/sys/kernel/mm/transparent_hugepage/enabled") {
+            Some(s) => {
+                let got = host::selected(&s).unwrap_or(&s).to_owned();
+                CheckResult::expect(name, got == expect, Status::Warn, got, expect)
             }
+            None => CheckResult::new(name, Status::Pass, "not supported", expect),
         }
+    }
 
-        errors
+    pub fn check_cpu_governor() -> CheckResult {
+        let name = "cpu-governor";
+        let expect = "performance";
+        let mut governors: Vec<String> = match fs::read_dir("/sys/devices/system/cpu") {
+            Ok(entries) => {
+                entries.filter_map(|e| e.ok())
+                    .filter_map(|e| {
+                        host::read_trimmed(e.path().join("cpufreq/scaling_governor"))
+                    })
+                    .collect()
+            }
+            Err(_) => vec![],
+        };
+        if governors.is_empty() {
+            // eg: VMs without cpufreq.
+            return CheckResult::new(name, Status::Pass, "no cpufreq", expect);
+        }
+        governors.sort();
+        governors.dedup();
+        let ok = governors.len() == 1 && governors[0] == expect;
+        CheckResult::expect(name, ok, Status::Warn, governors.join(","), expect)
+    }
+
+    pub fn check_device(db_path: &str) -> Vec<CheckResult> {
+        let mount = match host::mount_of(db_path) {
+            Some(m) => m,
+            None => return vec![CheckResult::new("mount", Status::Warn, "unknown", db_path)],
+        };
+        let mut results = vec![];
+
+        let fs_ok = mount.fs_type == "ext4" || mount.fs_type == "xfs";
+        results.push(CheckResult::expect("filesystem",
+                                         fs_ok,
+                                         Status::Warn,
+                                         mount.fs_type.clone(),
+                                         "ext4 or xfs"));
+        let mut wanted = vec!["noatime"];
+        if mount.fs_type == "ext4" {
+            wanted.push("nodelalloc");
+        }
+        let missing: Vec<&str> = wanted.iter()
+            .cloned()
+            .filter(|o| !mount.options.iter().any(|m| m == o))
+            .collect();
+        results.push(CheckResult::expect("mount-options",
+                                         missing.is_empty(),
+                                         Status::Warn,
+                                         mount.options.join(","),
+                                         &wanted.join(",")));
+
+        let disk = match host::block_device(&mount.device) {
+            Some(disk) => disk,
+            None => {
+                results.push(CheckResult::new("device", Status::Warn, mount.device, "a disk"));
+                return results;
+            }
+        };
+        let rotational = host::block_queue_param(&disk, "rotational");
+        results.push(CheckResult::expect("rotational",
+                                         rotational.as_ref().map(|r| r.as_str()) == Some("0"),
+                                         Status::Warn,
+                                         format!("{} {}",
+                                                 disk,
+                                                 rotational.unwrap_or("unknown".to_owned())),
+                                         "0, an ssd"));
+        let scheduler = host::block_queue_param(&disk, "scheduler").unwrap_or_default();
+        let selected = host::selected(&scheduler).unwrap_or(&scheduler).to_owned();
+        let ok = ["none", "noop", "deadline", "mq-deadline"].contains(&selected.as_str());
+        results.push(CheckResult::expect("io-scheduler",
+                                         ok,
+                                         Status::Warn,
+                                         format!("{} {}", disk, selected),
+                                         "none, noop or deadline"));
+        results
     }
 }
 
 #[cfg(target_os = "linux")]
-pub use self::check_kernel::check_kernel;
+pub use self::check_kernel::{check_kernel, check_transparent_hugepage, check_cpu_governor,
+                             check_device};
 
 #[cfg(not(target_os = "linux"))]
-pub fn check_kernel() -> Vec<Result<(), String>> {
+pub fn check_kernel() -> Vec<CheckResult> {
     Vec::new()
 }
 
-pub fn check_system_config() -> Result<(), String> {
-    if let Err(e) = check_max_open_fds(4096 as u64) {
-        return Err(format!("{:?}", e));
+#[cfg(not(target_os = "linux"))]
+pub fn check_transparent_hugepage() -> CheckResult {
+    CheckResult::new("transparent-hugepage", Status::Pass, "not linux", "never")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_cpu_governor() -> CheckResult {
+    CheckResult::new("cpu-governor", Status::Pass, "not linux", "performance")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_device(_: &str) -> Vec<CheckResult> {
+    Vec::new()
+}
+
+// Fails if the dataset does not fit, warns if there is no room for compactions to
+// rewrite it.
+pub fn check_disk_space(db_path: &str, dataset_bytes: u64) -> CheckResult {
+    let name = "disk-space";
+    let expect = format!(">= {} bytes, twice the dataset", dataset_bytes * 2);
+    match host::available_bytes(db_path) {
+        Some(avail) => {
+            let status = if avail < dataset_bytes {
+                Status::Fail
+            } else if avail < dataset_bytes * 2 {
+                Status::Warn
+            } else {
+                Status::Pass
+            };
+            CheckResult::new(name, status, format!("{} bytes", avail), &expect)
+        }
+        None => CheckResult::new(name, Status::Warn, "unknown", &expect),
     }
+}
 
-    // TODO: better iterater
-    for e in check_kernel() {
-        if let Err(e) = e {
-            return Err(format!("{:?}", e));
+// `db_path` and the expected `dataset_bytes` are checked when given.
+pub fn check_system(db_path: Option<&str>, dataset_bytes: Option<u64>) -> Vec<CheckResult> {
+    let mut results = vec![];
+    let expect = 4096;
+    let fds = match check_max_open_fds(expect) {
+        Ok(limit) => {
+            let observed = if limit == u64::max_value() {
+                "unlimited".to_owned()
+            } else if limit < expect {
+                format!("{}, raised to {}", limit, expect)
+            } else {
+                limit.to_string()
+            };
+            CheckResult::new("open-files", Status::Pass, observed, ">= 4096")
         }
+        Err(e) => CheckResult::new("open-files", Status::Fail, e, ">= 4096"),
+    };
+    results.push(fds);
+    results.extend(check_kernel());
+    results.push(check_transparent_hugepage());
+    results.push(check_cpu_governor());
+    if let Some(db_path) = db_path {
+        results.extend(check_device(db_path));
+        if let Some(bytes) = dataset_bytes {
+            results.push(check_disk_space(db_path, bytes));
+        }
+    }
+    if !cfg!(windows) {
+        let tz = env::var("TZ");
+        results.push(CheckResult::expect("TZ",
+                                         tz.is_ok(),
+                                         Status::Fail,
+                                         tz.unwrap_or("missing".to_owned()),
+                                         "set"));
+    }
+    results
+}
+
+pub fn print_checks(results: &[CheckResult]) {
+    println!("{:<6} {:<32} {:<40} {}", "status", "check", "observed", "expect");
+    for r in results {
+        println!("{:<6} {:<32} {:<40} {}",
+                 r.status.name(),
+                 r.name,
+                 r.observed,
+                 r.expect);
     }
+}
+
+// eg: `[vm.swappiness]` with status, observed and expect.
+pub fn checks_to_toml(results: &[CheckResult]) -> toml::Value {
+    let table = results.iter()
+        .map(|r| {
+            let mut t = BTreeMap::new();
+            t.insert("status".to_owned(),
+                     toml::Value::String(r.status.name().to_owned()));
+            t.insert("observed".to_owned(), toml::Value::String(r.observed.clone()));
+            t.insert("expect".to_owned(), toml::Value::String(r.expect.clone()));
+            (r.name.clone(), toml::Value::Table(t))
+        })
+        .collect();
+    toml::Value::Table(table)
+}
+
+// The checks not passed at or above `threshold`.
+pub fn failed(results: &[CheckResult], threshold: Status) -> Vec<&CheckResult> {
+    results.iter().filter(|r| r.status != Status::Pass && r.status >= threshold).collect()
+}
 
-    if !cfg!(windows) && env::var("TZ").is_err() {
-        return Err("environment variable `TZ` is missing".to_owned());
+// Run every check and print them all, then fail if any is at or above `threshold`.
+pub fn check_system_config(db_path: Option<&str>,
+                           dataset_bytes: Option<u64>,
+                           threshold: Status)
+                           -> Result<Vec<CheckResult>, String> {
+    let results = check_system(db_path, dataset_bytes);
+    print_checks(&results);
+    let failed: Vec<String> = failed(&results, threshold).iter().map(|r| r.to_string()).collect();
+    if !failed.is_empty() {
+        return Err(failed.join("\n"));
     }
-    Ok(())
+    Ok(results)
 }
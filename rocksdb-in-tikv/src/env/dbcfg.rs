@@ -11,23 +11,128 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::process;
-use std::fs::File;
-use std::io::Read;
+use std::collections::BTreeSet;
+use std::i32;
 use toml;
 use rocksdb::{Options as RocksdbOptions, BlockBasedOptions, DBCompressionType, CompactionPriority,
-              DBRecoveryMode};
-use super::helper::{get_toml_boolean, get_toml_int, get_toml_string};
+              DBRecoveryMode, DBCompactionStyle, DBUniversalCompactionOptions,
+              FifoCompactionOptions, Cache, LRUCacheOptions};
+use super::helper::{ConfigError, ConfigErrors, UnknownKey, edit_distance, get_toml_boolean,
+                    get_toml_float, get_toml_int, get_toml_string, load_toml, merge_toml,
+                    parse_override, set_toml_value, toml_leaf_keys};
+use super::options;
+use super::prefix::PrefixExtractor;
 
 const SEC_TO_MS: i64 = 1000;
 const UNIT: u64 = 1;
 const DATA_MAGNITUDE: u64 = 1024;
 const KB: u64 = UNIT * DATA_MAGNITUDE;
 const MB: u64 = KB * DATA_MAGNITUDE;
+const GB: u64 = MB * DATA_MAGNITUDE;
+
+// RocksDB's default, the hard limit must not be below it when only the hard one is set.
+const ROCKSDB_SOFT_PENDING_COMPACTION_BYTES_LIMIT: i64 = 64 * GB as i64;
+
+// Reads options out of the config, and records the value each of them resolved to,
+// defaults included, so a run can tell what it actually used. Bad values are
+// collected instead of failing at the first one.
+struct ConfigReader<'a> {
+    config: &'a toml::Value,
+    resolved: toml::Value,
+    errors: Vec<ConfigError>,
+    // Every option asked for, whatever it resolved to.
+    known: BTreeSet<String>,
+}
+
+impl<'a> ConfigReader<'a> {
+    fn new(config: &'a toml::Value) -> ConfigReader<'a> {
+        ConfigReader {
+            config: config,
+            resolved: toml::Value::Table(toml::Table::new()),
+            errors: vec![],
+            known: BTreeSet::new(),
+        }
+    }
+
+    // Mark an option known, though it is not used with the current settings.
+    fn skip(&mut self, name: &str) {
+        self.known.insert(name.to_owned());
+    }
+
+    fn record<T, F>(&mut self, res: Result<T, ConfigError>, name: &str, to_toml: F) -> Option<T>
+        where F: Fn(&T) -> toml::Value
+    {
+        self.skip(name);
+        match res {
+            Ok(v) => {
+                // A path through a non-table value fails at reading already.
+                let _ = set_toml_value(&mut self.resolved, name, to_toml(&v));
+                Some(v)
+            }
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+
+    fn get_int(&mut self, name: &str, default: Option<i64>) -> i64 {
+        let res = get_toml_int(self.config, name, default);
+        self.record(res, name, |v| toml::Value::Integer(*v)).or(default).unwrap_or(0)
+    }
+
+    // For options left to RocksDB's default unless set: None if `name` is missing.
+    fn get_int_opt(&mut self, name: &str) -> Option<i64> {
+        if self.config.lookup(name).is_none() {
+            self.skip(name);
+            return None;
+        }
+        let res = get_toml_int(self.config, name, None);
+        self.record(res, name, |v| toml::Value::Integer(*v))
+    }
 
-fn exit_with_err(msg: String) -> ! {
-    print!("{}", msg);
-    process::exit(1)
+    fn get_boolean_opt(&mut self, name: &str) -> Option<bool> {
+        if self.config.lookup(name).is_none() {
+            self.skip(name);
+            return None;
+        }
+        let res = get_toml_boolean(self.config, name, None);
+        self.record(res, name, |v| toml::Value::Boolean(*v))
+    }
+
+    fn get_boolean(&mut self, name: &str, default: Option<bool>) -> bool {
+        let res = get_toml_boolean(self.config, name, default);
+        self.record(res, name, |v| toml::Value::Boolean(*v)).or(default).unwrap_or(false)
+    }
+
+    fn get_float(&mut self, name: &str, default: Option<f64>) -> f64 {
+        let res = get_toml_float(self.config, name, default);
+        self.record(res, name, |v| toml::Value::Float(*v)).or(default).unwrap_or(0.0)
+    }
+
+    fn get_string(&mut self, name: &str, default: Option<String>) -> String {
+        let res = get_toml_string(self.config, name, default.clone());
+        self.record(res, name, |v| toml::Value::String(v.clone()))
+            .or(default)
+            .unwrap_or_default()
+    }
+
+    // Check a value read by `name`, recording `expected` as an error if it fails.
+    fn check<T>(&mut self, name: &str, expected: &str, res: Result<T, String>) -> Option<T> {
+        match res {
+            Ok(v) => Some(v),
+            Err(_) => {
+                self.invalid(name, expected);
+                None
+            }
+        }
+    }
+
+    fn invalid(&mut self, name: &str, expected: &str) {
+        let value = self.config.lookup(name).or_else(|| self.resolved.lookup(name));
+        let e = ConfigError::new(name, expected, value);
+        self.errors.push(e);
+    }
 }
 
 fn align_to_mb(n: u64) -> u64 {
@@ -73,100 +178,142 @@ fn parse_rocksdb_compaction_priority(priority: i64) -> Result<CompactionPriority
     }
 }
 
-fn get_rocksdb_db_option(config: &toml::Value) -> RocksdbOptions {
+fn parse_rocksdb_compaction_style(style: i64) -> Result<DBCompactionStyle, String> {
+    match style {
+        0 => Ok(DBCompactionStyle::Level),
+        1 => Ok(DBCompactionStyle::Universal),
+        2 => Ok(DBCompactionStyle::Fifo),
+        _ => Err(format!("not valid compaction style: {}", style)),
+    }
+}
+
+fn get_rocksdb_db_option(cfg: &mut ConfigReader) -> RocksdbOptions {
     let mut opts = RocksdbOptions::new();
-    let rmode = get_toml_int(config, "rocksdb.wal-recovery-mode", Some(2));
-    let wal_recovery_mode = parse_rocksdb_wal_recovery_mode(rmode)
-        .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
-    opts.set_wal_recovery_mode(wal_recovery_mode);
+    let rmode = cfg.get_int("rocksdb.wal-recovery-mode", Some(2));
+    if let Some(mode) = cfg.check("rocksdb.wal-recovery-mode",
+                                  "recovery mode in [0, 3]",
+                                  parse_rocksdb_wal_recovery_mode(rmode)) {
+        opts.set_wal_recovery_mode(mode);
+    }
 
-    let wal_dir = get_toml_string(config, "rocksdb.wal-dir", Some("".to_owned()));
+    let wal_dir = cfg.get_string("rocksdb.wal-dir", Some("".to_owned()));
     if !wal_dir.is_empty() {
         opts.set_wal_dir(&wal_dir)
     };
 
-    let wal_ttl_seconds = get_toml_int(config, "rocksdb.wal-ttl-seconds", Some(0));
+    let wal_ttl_seconds = cfg.get_int("rocksdb.wal-ttl-seconds", Some(0));
     opts.set_wal_ttl_seconds(wal_ttl_seconds as u64);
 
-    let wal_size_limit = get_toml_int(config, "rocksdb.wal-size-limit", Some(0));
+    let wal_size_limit = cfg.get_int("rocksdb.wal-size-limit", Some(0));
     // return size in MB
     let wal_size_limit_mb = align_to_mb(wal_size_limit as u64) / MB;
     opts.set_wal_size_limit_mb(wal_size_limit_mb as u64);
 
-    let max_total_wal_size = get_toml_int(config,
-                                          "rocksdb.max-total-wal-size",
-                                          Some(4 * 1024 * 1024 * 1024));
+    let max_total_wal_size = cfg.get_int("rocksdb.max-total-wal-size",
+                                         Some(4 * 1024 * 1024 * 1024));
     opts.set_max_total_wal_size(max_total_wal_size as u64);
 
     let max_background_compactions =
-        get_toml_int(config, "rocksdb.max-background-compactions", Some(6));
+        cfg.get_int("rocksdb.max-background-compactions", Some(6));
     opts.set_max_background_compactions(max_background_compactions as i32);
 
-    let max_background_flushes = get_toml_int(config, "rocksdb.max-background-flushes", Some(2));
+    let max_background_flushes = cfg.get_int("rocksdb.max-background-flushes", Some(2));
     opts.set_max_background_flushes(max_background_flushes as i32);
 
-    let base_bg_compactions = get_toml_int(config, "rocksdb.base-background-compactions", Some(1));
+    let base_bg_compactions = cfg.get_int("rocksdb.base-background-compactions", Some(1));
     opts.set_base_background_compactions(base_bg_compactions as i32);
 
-    let max_manifest_file_size = get_toml_int(config,
-                                              "rocksdb.max-manifest-file-size",
-                                              Some(20 * 1024 * 1024));
+    let max_manifest_file_size = cfg.get_int("rocksdb.max-manifest-file-size",
+                                             Some(20 * 1024 * 1024));
     opts.set_max_manifest_file_size(max_manifest_file_size as u64);
 
-    let create_if_missing = get_toml_boolean(config, "rocksdb.create-if-missing", Some(true));
+    let create_if_missing = cfg.get_boolean("rocksdb.create-if-missing", Some(true));
     opts.create_if_missing(create_if_missing);
 
-    let max_open_files = get_toml_int(config, "rocksdb.max-open-files", Some(40960));
+    let max_open_files = cfg.get_int("rocksdb.max-open-files", Some(40960));
     opts.set_max_open_files(max_open_files as i32);
 
-    let enable_statistics = get_toml_boolean(config, "rocksdb.enable-statistics", Some(true));
+    let enable_statistics = cfg.get_boolean("rocksdb.enable-statistics", Some(true));
     if enable_statistics {
         opts.enable_statistics();
         let stats_dump_period_sec =
-            get_toml_int(config, "rocksdb.stats-dump-period-sec", Some(600));
+            cfg.get_int("rocksdb.stats-dump-period-sec", Some(600));
         opts.set_stats_dump_period_sec(stats_dump_period_sec as usize);
+    } else {
+        cfg.skip("rocksdb.stats-dump-period-sec");
     }
 
     let compaction_readahead_size =
-        get_toml_int(config, "rocksdb.compaction-readahead-size", Some(0));
+        cfg.get_int("rocksdb.compaction-readahead-size", Some(0));
     opts.set_compaction_readahead_size(compaction_readahead_size as u64);
 
-    let max_file_size = get_toml_int(config, "rocksdb.info-log-max-size", Some(0));
+    let max_file_size = cfg.get_int("rocksdb.info-log-max-size", Some(0));
     opts.set_max_log_file_size(max_file_size as u64);
 
     // RocksDB needs seconds, but here we will get milliseconds.
-    let roll_time_secs = get_toml_int(config, "rocksdb.info-log-roll-time", Some(0)) / SEC_TO_MS;
+    let roll_time_secs = cfg.get_int("rocksdb.info-log-roll-time", Some(0)) / SEC_TO_MS;
     opts.set_log_file_time_to_roll(roll_time_secs as u64);
 
-    let info_log_dir = get_toml_string(config, "rocksdb.info-log-dir", Some("".to_owned()));
-    if !info_log_dir.is_empty() {
-        opts.create_info_log(&info_log_dir).unwrap_or_else(|e| {
-            panic!("create RocksDB info log {} error {:?}", info_log_dir, e);
-        })
-    }
-
-    let rate_bytes_per_sec = get_toml_int(config, "rocksdb.rate-bytes-per-sec", Some(0));
+    let rate_bytes_per_sec = cfg.get_int("rocksdb.rate-bytes-per-sec", Some(0));
     if rate_bytes_per_sec > 0 {
         opts.set_ratelimiter(rate_bytes_per_sec as i64);
     }
 
-    let max_sub_compactions = get_toml_int(config, "rocksdb.max-sub-compactions", Some(1));
+    let max_sub_compactions = cfg.get_int("rocksdb.max-sub-compactions", Some(1));
     opts.set_max_subcompactions(max_sub_compactions as u32);
 
-    let writable_file_max_buffer_size = get_toml_int(config,
-                                                     "rocksdb.writable-file-max-buffer-size",
-                                                     Some(1024 * 1024));
+    let writable_file_max_buffer_size = cfg.get_int("rocksdb.writable-file-max-buffer-size",
+                                                    Some(1024 * 1024));
     opts.set_writable_file_max_buffer_size(writable_file_max_buffer_size as i32);
 
-    let direct_io = get_toml_boolean(config,
-                                     "rocksdb.use-direct-io-for-flush-and-compaction",
-                                     Some(false));
+    let direct_io = cfg.get_boolean("rocksdb.use-direct-io-for-flush-and-compaction",
+                                    Some(false));
     opts.set_use_direct_io_for_flush_and_compaction(direct_io);
 
+    // The options below keep RocksDB's defaults unless set, so that adding them did not
+    // change the runs of the former configs.
+    if let Some(direct_reads) = cfg.get_boolean_opt("rocksdb.use-direct-io-for-reads") {
+        opts.set_use_direct_reads(direct_reads);
+    }
+
+    if let Some(bytes_per_sync) = cfg.get_int_opt("rocksdb.bytes-per-sync") {
+        if bytes_per_sync < 0 {
+            cfg.invalid("rocksdb.bytes-per-sync", "non-negative int");
+        } else {
+            opts.set_bytes_per_sync(bytes_per_sync as u64);
+        }
+    }
+
+    if let Some(wal_bytes_per_sync) = cfg.get_int_opt("rocksdb.wal-bytes-per-sync") {
+        if wal_bytes_per_sync < 0 {
+            cfg.invalid("rocksdb.wal-bytes-per-sync", "non-negative int");
+        } else {
+            opts.set_wal_bytes_per_sync(wal_bytes_per_sync as u64);
+        }
+    }
+
+    if let Some(pipelined_write) = cfg.get_boolean_opt("rocksdb.enable-pipelined-write") {
+        opts.enable_pipelined_write(pipelined_write);
+    }
+
+    if let Some(concurrent_memtable_write) =
+           cfg.get_boolean_opt("rocksdb.allow-concurrent-memtable-write") {
+        opts.allow_concurrent_memtable_write(concurrent_memtable_write);
+    }
+
+    // RocksDB only takes it when max-background-compactions and flushes are not set.
+    if let Some(max_background_jobs) = cfg.get_int_opt("rocksdb.max-background-jobs") {
+        if max_background_jobs <= 0 {
+            cfg.invalid("rocksdb.max-background-jobs", "positive int");
+        } else {
+            opts.set_max_background_jobs(max_background_jobs as i32);
+        }
+    }
+
     opts
 }
 
-// TODO: drop default values, use (base file + tuning file) instead
+// Defaults under all the config layers.
 struct CfOptValues {
     pub block_size: i64,
     pub block_cache_size: i64,
@@ -174,6 +321,9 @@ struct CfOptValues {
     pub use_bloom_filter: bool,
     pub whole_key_filtering: bool,
     pub bloom_bits_per_key: i64,
+    pub prefix_extractor: String,
+    pub prefix_extractor_len: i64,
+    pub memtable_prefix_bloom_size_ratio: f64,
     pub block_based_filter: bool,
     pub compression_per_level: String,
     pub write_buffer_size: i64,
@@ -185,6 +335,13 @@ struct CfOptValues {
     pub level_zero_slowdown_writes_trigger: i64,
     pub level_zero_stop_writes_trigger: i64,
     pub compaction_priority: i64,
+    pub compaction_style: i64,
+    pub universal_size_ratio: i64,
+    pub universal_min_merge_width: i64,
+    pub universal_max_merge_width: i64,
+    pub universal_max_size_amplification_percent: i64,
+    pub fifo_max_table_files_size: i64,
+    pub fifo_ttl: i64,
 }
 
 // TODO: verify: (TiDB default values) == (rocksdb default values)
@@ -197,6 +354,9 @@ impl Default for CfOptValues {
             use_bloom_filter: false,
             whole_key_filtering: true,
             bloom_bits_per_key: 10,
+            prefix_extractor: String::from("none"),
+            prefix_extractor_len: 0,
+            memtable_prefix_bloom_size_ratio: 0.0,
             block_based_filter: false,
             compression_per_level: String::from("no:no:lz4:lz4:lz4:lz4:lz4"),
             write_buffer_size: 128 * MB as i64,
@@ -208,119 +368,440 @@ impl Default for CfOptValues {
             level_zero_slowdown_writes_trigger: 20,
             level_zero_stop_writes_trigger: 36,
             compaction_priority: 0,
+            compaction_style: 0,
+            universal_size_ratio: 1,
+            universal_min_merge_width: 2,
+            universal_max_merge_width: i32::MAX as i64,
+            universal_max_size_amplification_percent: 200,
+            fifo_max_table_files_size: 1024 * MB as i64,
+            fifo_ttl: 0,
+        }
+    }
+}
+
+// One block cache shared by all the cfs, like TiKV does, if `storage.block-cache.shared`.
+fn get_shared_block_cache(cfg: &mut ConfigReader) -> Option<Cache> {
+    let keys = ["storage.block-cache.capacity",
+                "storage.block-cache.num-shard-bits",
+                "storage.block-cache.strict-capacity-limit",
+                "storage.block-cache.high-pri-pool-ratio"];
+    if !cfg.get_boolean("storage.block-cache.shared", Some(false)) {
+        for key in &keys {
+            cfg.skip(key);
+        }
+        return None;
+    }
+
+    let mut cache_opts = LRUCacheOptions::new();
+    let capacity = cfg.get_int(keys[0], Some(GB as i64));
+    cache_opts.set_capacity(capacity as usize);
+    let num_shard_bits = cfg.get_int(keys[1], Some(6));
+    // RocksDB takes less than 20 shard bits.
+    if num_shard_bits < 0 || num_shard_bits >= 20 {
+        cfg.invalid(keys[1], "int in [0, 20)");
+    }
+    cache_opts.set_num_shard_bits(num_shard_bits as i32);
+    let strict_capacity_limit = cfg.get_boolean(keys[2], Some(false));
+    cache_opts.set_strict_capacity_limit(strict_capacity_limit);
+    let high_pri_pool_ratio = cfg.get_float(keys[3], Some(0.0));
+    if high_pri_pool_ratio < 0.0 || high_pri_pool_ratio > 1.0 {
+        cfg.invalid(keys[3], "float in [0, 1]");
+    }
+    cache_opts.set_high_pri_pool_ratio(high_pri_pool_ratio);
+    Some(Cache::new_lru_cache(cache_opts))
+}
+
+// eg: `prefix-extractor = "fixed"` with `prefix-extractor-len = 8`.
+fn get_prefix_extractor(cfg: &mut ConfigReader,
+                        cf: &str,
+                        default_values: &CfOptValues)
+                        -> Option<PrefixExtractor> {
+    let prefix = String::from("rocksdb.") + cf + ".";
+    let tp_name = prefix.clone() + "prefix-extractor";
+    let len_name = prefix.clone() + "prefix-extractor-len";
+    let tp = cfg.get_string(&tp_name, Some(default_values.prefix_extractor.clone()));
+    let extractor = match cfg.check(&tp_name,
+                                    "prefix extractor, fixed, capped or none",
+                                    PrefixExtractor::parse(&tp, 0)) {
+        Some(Some(_)) => {
+            let len = cfg.get_int(&len_name, Some(default_values.prefix_extractor_len));
+            if len <= 0 {
+                cfg.invalid(&len_name, "positive int");
+            }
+            PrefixExtractor::parse(&tp, len as usize).unwrap()
         }
+        _ => None,
+    };
+    if extractor.is_none() {
+        cfg.skip(&len_name);
     }
+    extractor
 }
 
-fn get_rocksdb_cf_option(config: &toml::Value,
+fn get_rocksdb_cf_option(cfg: &mut ConfigReader,
                          cf: &str,
-                         default_values: CfOptValues)
+                         default_values: CfOptValues,
+                         shared_cache: Option<&Cache>,
+                         prefix_extractor: Option<PrefixExtractor>)
                          -> RocksdbOptions {
     let prefix = String::from("rocksdb.") + cf + ".";
     let mut block_base_opts = BlockBasedOptions::new();
-    let block_size = get_toml_int(config,
-                                  (prefix.clone() + "block-size").as_str(),
-                                  Some(default_values.block_size));
+    let block_size = cfg.get_int((prefix.clone() + "block-size").as_str(),
+                                 Some(default_values.block_size));
     block_base_opts.set_block_size(block_size as usize);
-    let block_cache_size = get_toml_int(config,
-                                        (prefix.clone() + "block-cache-size").as_str(),
-                                        Some(default_values.block_cache_size));
-    block_base_opts.set_lru_cache(block_cache_size as usize);
+    match shared_cache {
+        Some(cache) => {
+            block_base_opts.set_block_cache(cache);
+            cfg.skip((prefix.clone() + "block-cache-size").as_str());
+        }
+        None => {
+            let block_cache_size = cfg.get_int((prefix.clone() + "block-cache-size").as_str(),
+                                               Some(default_values.block_cache_size));
+            block_base_opts.set_lru_cache(block_cache_size as usize);
+        }
+    }
 
     let cache_index_and_filter =
-        get_toml_boolean(config,
-                         (prefix.clone() + "cache-index-and-filter-blocks").as_str(),
-                         Some(default_values.cache_index_and_filter_blocks));
+        cfg.get_boolean((prefix.clone() + "cache-index-and-filter-blocks").as_str(),
+                        Some(default_values.cache_index_and_filter_blocks));
     block_base_opts.set_cache_index_and_filter_blocks(cache_index_and_filter);
 
-    if default_values.use_bloom_filter {
-        let bloom_bits_per_key = get_toml_int(config,
-                                              (prefix.clone() + "bloom-filter-bits-per-key")
-                                                  .as_str(),
-                                              Some(default_values.bloom_bits_per_key));
-        let block_based_filter = get_toml_boolean(config,
-                                                  (prefix.clone() + "block-based-bloom-filter")
-                                                      .as_str(),
-                                                  Some(default_values.block_based_filter));
-        block_base_opts.set_bloom_filter(bloom_bits_per_key as i32, block_based_filter);
+    // Left to RocksDB's default unless set, as the options of the db.
+    if let Some(pin_l0) =
+           cfg.get_boolean_opt((prefix.clone() + "pin-l0-filter-and-index-blocks").as_str()) {
+        block_base_opts.set_pin_l0_filter_and_index_blocks_in_cache(pin_l0);
+    }
+
+    let read_amp_name = prefix.clone() + "read-amp-bytes-per-bit";
+    if let Some(bytes_per_bit) = cfg.get_int_opt(&read_amp_name) {
+        // 0 disables it, otherwise RocksDB needs a power of 2.
+        if bytes_per_bit < 0 ||
+           (bytes_per_bit as u64 & (bytes_per_bit as u64).wrapping_sub(1)) != 0 {
+            cfg.invalid(&read_amp_name, "0 or a power of 2");
+        } else {
+            block_base_opts.set_read_amp_bytes_per_bit(bytes_per_bit as u32);
+        }
+    }
 
-        block_base_opts.set_whole_key_filtering(default_values.whole_key_filtering);
+    let use_bloom_filter = cfg.get_boolean((prefix.clone() + "use-bloom-filter").as_str(),
+                                           Some(default_values.use_bloom_filter));
+    let bits_name = prefix.clone() + "bloom-filter-bits-per-key";
+    let block_based_name = prefix.clone() + "block-based-bloom-filter";
+    if use_bloom_filter {
+        let bloom_bits_per_key = cfg.get_int(&bits_name, Some(default_values.bloom_bits_per_key));
+        let block_based_filter = cfg.get_boolean(&block_based_name,
+                                                 Some(default_values.block_based_filter));
+        block_base_opts.set_bloom_filter(bloom_bits_per_key as i32, block_based_filter);
+    } else {
+        // Set but without a filter to take them, likely a mistake in the config.
+        for name in &[bits_name, block_based_name] {
+            cfg.skip(name);
+            if cfg.config.lookup(name).is_some() {
+                cfg.invalid(name, "not set when use-bloom-filter is false");
+            }
+        }
     }
+
+    // RocksDB takes it with or without a bloom filter.
+    let whole_key_filtering = cfg.get_boolean((prefix.clone() + "whole-key-filtering").as_str(),
+                                              Some(default_values.whole_key_filtering));
+    block_base_opts.set_whole_key_filtering(whole_key_filtering);
     let mut opts = RocksdbOptions::new();
     opts.set_block_based_table_factory(&block_base_opts);
 
-    let cpl = get_toml_string(config,
-                              (prefix.clone() + "compression-per-level").as_str(),
-                              Some(default_values.compression_per_level.clone()));
-    let per_level_compression = parse_rocksdb_per_level_compression(&cpl)
-        .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
-    opts.compression_per_level(&per_level_compression);
+    if let Some(extractor) = prefix_extractor {
+        let res = opts.set_prefix_extractor(extractor.name(), extractor.transform());
+        cfg.check((prefix.clone() + "prefix-extractor").as_str(),
+                  "a prefix extractor RocksDB takes",
+                  res);
+    }
+    let bloom_ratio_name = prefix.clone() + "memtable-prefix-bloom-size-ratio";
+    let bloom_ratio = cfg.get_float(&bloom_ratio_name,
+                                    Some(default_values.memtable_prefix_bloom_size_ratio));
+    // RocksDB caps it at 0.25 of the write buffer.
+    if bloom_ratio < 0.0 || bloom_ratio > 0.25 {
+        cfg.invalid(&bloom_ratio_name, "float in [0, 0.25]");
+    }
+    opts.set_memtable_prefix_bloom_size_ratio(bloom_ratio);
+
+    let cpl = cfg.get_string((prefix.clone() + "compression-per-level").as_str(),
+                             Some(default_values.compression_per_level.clone()));
+    if let Some(per_level_compression) =
+        cfg.check((prefix.clone() + "compression-per-level").as_str(),
+                  "compression types joined by ':', eg: no:no:lz4:zstd",
+                  parse_rocksdb_per_level_compression(&cpl)) {
+        opts.compression_per_level(&per_level_compression);
+    }
 
-    let write_buffer_size = get_toml_int(config,
-                                         (prefix.clone() + "write-buffer-size").as_str(),
-                                         Some(default_values.write_buffer_size));
+    let write_buffer_size = cfg.get_int((prefix.clone() + "write-buffer-size").as_str(),
+                                        Some(default_values.write_buffer_size));
     opts.set_write_buffer_size(write_buffer_size as u64);
 
-    let max_write_buffer_number = get_toml_int(config,
-                                               (prefix.clone() + "max-write-buffer-number")
-                                                   .as_str(),
-                                               Some(default_values.max_write_buffer_number));
+    let max_write_buffer_number =
+        cfg.get_int((prefix.clone() + "max-write-buffer-number").as_str(),
+                    Some(default_values.max_write_buffer_number));
     opts.set_max_write_buffer_number(max_write_buffer_number as i32);
 
     let min_write_buffer_number_to_merge =
-        get_toml_int(config,
-                     (prefix.clone() + "min-write-buffer-number-to-merge").as_str(),
-                     Some(default_values.min_write_buffer_number_to_merge));
+        cfg.get_int((prefix.clone() + "min-write-buffer-number-to-merge").as_str(),
+                    Some(default_values.min_write_buffer_number_to_merge));
     opts.set_min_write_buffer_number_to_merge(min_write_buffer_number_to_merge as i32);
 
-    let max_bytes_for_level_base = get_toml_int(config,
-                                                (prefix.clone() + "max-bytes-for-level-base")
-                                                    .as_str(),
-                                                Some(default_values.max_bytes_for_level_base));
+    let max_bytes_for_level_base =
+        cfg.get_int((prefix.clone() + "max-bytes-for-level-base").as_str(),
+                    Some(default_values.max_bytes_for_level_base));
     opts.set_max_bytes_for_level_base(max_bytes_for_level_base as u64);
 
-    let target_file_size_base = get_toml_int(config,
-                                             (prefix.clone() + "target-file-size-base").as_str(),
-                                             Some(default_values.target_file_size_base));
+    let target_file_size_base = cfg.get_int((prefix.clone() + "target-file-size-base").as_str(),
+                                            Some(default_values.target_file_size_base));
     opts.set_target_file_size_base(target_file_size_base as u64);
 
     let level_zero_file_num_compaction_trigger =
-        get_toml_int(config,
-                     (prefix.clone() + "level0-file-num-compaction-trigger").as_str(),
-                     Some(default_values.level_zero_file_num_compaction_trigger));
+        cfg.get_int((prefix.clone() + "level0-file-num-compaction-trigger").as_str(),
+                    Some(default_values.level_zero_file_num_compaction_trigger));
     opts.set_level_zero_file_num_compaction_trigger(level_zero_file_num_compaction_trigger as i32);
 
     let level_zero_slowdown_writes_trigger =
-        get_toml_int(config,
-                     (prefix.clone() + "level0-slowdown-writes-trigger").as_str(),
-                     Some(default_values.level_zero_slowdown_writes_trigger));
+        cfg.get_int((prefix.clone() + "level0-slowdown-writes-trigger").as_str(),
+                    Some(default_values.level_zero_slowdown_writes_trigger));
     opts.set_level_zero_slowdown_writes_trigger(level_zero_slowdown_writes_trigger as i32);
 
     let level_zero_stop_writes_trigger =
-        get_toml_int(config,
-                     (prefix.clone() + "level0-stop-writes-trigger").as_str(),
-                     Some(default_values.level_zero_stop_writes_trigger));
+        cfg.get_int((prefix.clone() + "level0-stop-writes-trigger").as_str(),
+                    Some(default_values.level_zero_stop_writes_trigger));
     opts.set_level_zero_stop_writes_trigger(level_zero_stop_writes_trigger as i32);
 
-    let compaction_priority = get_toml_int(config,
-                                        (prefix.clone() + "compaction-priority").as_str(),
-                                        Some(default_values.compaction_priority));
-    let compaction_priority = parse_rocksdb_compaction_priority(compaction_priority)
-        .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
-    opts.compaction_priority(compaction_priority);
+    let compaction_priority = cfg.get_int((prefix.clone() + "compaction-priority").as_str(),
+                                          Some(default_values.compaction_priority));
+    if let Some(priority) = cfg.check((prefix.clone() + "compaction-priority").as_str(),
+                                      "compaction priority in [0, 3]",
+                                      parse_rocksdb_compaction_priority(compaction_priority)) {
+        opts.compaction_priority(priority);
+    }
+
+    let compaction_style = cfg.get_int((prefix.clone() + "compaction-style").as_str(),
+                                       Some(default_values.compaction_style));
+    let style = cfg.check((prefix.clone() + "compaction-style").as_str(),
+                          "compaction style in [0, 2], level, universal or fifo",
+                          parse_rocksdb_compaction_style(compaction_style));
+    if let Some(dynamic_level_bytes) =
+           cfg.get_boolean_opt((prefix.clone() + "level-compaction-dynamic-level-bytes")
+               .as_str()) {
+        opts.set_level_compaction_dynamic_level_bytes(dynamic_level_bytes);
+    }
+
+    let num_levels_name = prefix.clone() + "num-levels";
+    if let Some(num_levels) = cfg.get_int_opt(&num_levels_name) {
+        if num_levels < 1 {
+            cfg.invalid(&num_levels_name, "positive int");
+        } else {
+            opts.set_num_levels(num_levels as i32);
+        }
+    }
+
+    let multiplier_name = prefix.clone() + "max-bytes-for-level-multiplier";
+    if let Some(multiplier) = cfg.get_int_opt(&multiplier_name) {
+        if multiplier < 1 {
+            cfg.invalid(&multiplier_name, "positive int");
+        } else {
+            opts.set_max_bytes_for_level_multiplier(multiplier as i32);
+        }
+    }
+
+    let soft_limit_name = prefix.clone() + "soft-pending-compaction-bytes-limit";
+    let soft_limit = cfg.get_int_opt(&soft_limit_name);
+    if let Some(soft_limit) = soft_limit {
+        if soft_limit < 0 {
+            cfg.invalid(&soft_limit_name, "non-negative int");
+        } else {
+            opts.set_soft_pending_compaction_bytes_limit(soft_limit as u64);
+        }
+    }
+    let hard_limit_name = prefix.clone() + "hard-pending-compaction-bytes-limit";
+    if let Some(hard_limit) = cfg.get_int_opt(&hard_limit_name) {
+        // 0 disables the limit.
+        let soft_limit = soft_limit.unwrap_or(ROCKSDB_SOFT_PENDING_COMPACTION_BYTES_LIMIT);
+        if hard_limit < 0 || hard_limit != 0 && hard_limit < soft_limit {
+            cfg.invalid(&hard_limit_name,
+                        "0 or no less than soft-pending-compaction-bytes-limit");
+        } else {
+            opts.set_hard_pending_compaction_bytes_limit(hard_limit as u64);
+        }
+    }
+
+    if let Some(disable_auto_compactions) =
+           cfg.get_boolean_opt((prefix.clone() + "disable-auto-compactions").as_str()) {
+        opts.set_disable_auto_compactions(disable_auto_compactions);
+    }
+
+    if let Some(optimize_filters_for_hits) =
+           cfg.get_boolean_opt((prefix.clone() + "optimize-filters-for-hits").as_str()) {
+        opts.set_optimize_filters_for_hits(optimize_filters_for_hits);
+    }
+
+    // The options of the other styles are known but not read, so they stay out of the
+    // resolved config.
+    let universal_keys = ["universal-size-ratio",
+                          "universal-min-merge-width",
+                          "universal-max-merge-width",
+                          "universal-max-size-amplification-percent"];
+    let fifo_keys = ["fifo-max-table-files-size", "fifo-ttl"];
+    match style {
+        Some(DBCompactionStyle::Universal) => {
+            let mut uco = DBUniversalCompactionOptions::new();
+            let size_ratio = cfg.get_int((prefix.clone() + universal_keys[0]).as_str(),
+                                         Some(default_values.universal_size_ratio));
+            uco.set_size_ratio(size_ratio as i32);
+            let min_merge_width = cfg.get_int((prefix.clone() + universal_keys[1]).as_str(),
+                                              Some(default_values.universal_min_merge_width));
+            uco.set_min_merge_width(min_merge_width as i32);
+            let max_merge_width = cfg.get_int((prefix.clone() + universal_keys[2]).as_str(),
+                                              Some(default_values.universal_max_merge_width));
+            uco.set_max_merge_width(max_merge_width as i32);
+            let max_size_amp =
+                cfg.get_int((prefix.clone() + universal_keys[3]).as_str(),
+                            Some(default_values.universal_max_size_amplification_percent));
+            uco.set_max_size_amplification_percent(max_size_amp as i32);
+            opts.set_universal_compaction_options(&uco);
+        }
+        Some(DBCompactionStyle::Fifo) => {
+            let mut fifo = FifoCompactionOptions::new();
+            let max_table_files_size =
+                cfg.get_int((prefix.clone() + fifo_keys[0]).as_str(),
+                            Some(default_values.fifo_max_table_files_size));
+            fifo.set_max_table_files_size(max_table_files_size as u64);
+            let ttl = cfg.get_int((prefix.clone() + fifo_keys[1]).as_str(),
+                                  Some(default_values.fifo_ttl));
+            // RocksDB refuses to open with a FIFO ttl unless all the files are kept open.
+            let max_open_files = cfg.resolved
+                .lookup("rocksdb.max-open-files")
+                .and_then(|v| v.as_integer());
+            if ttl > 0 && max_open_files != Some(-1) {
+                cfg.invalid("rocksdb.max-open-files", "-1 with a fifo-ttl");
+            }
+            fifo.set_ttl(ttl as u64);
+            opts.set_fifo_compaction_options(fifo);
+        }
+        _ => {}
+    }
+    if let Some(style) = style {
+        opts.set_compaction_style(style);
+    }
+    for key in universal_keys.iter().chain(fifo_keys.iter()) {
+        cfg.skip((prefix.clone() + key).as_str());
+    }
 
     opts
 }
 
-pub fn get_db_config(base: &str) -> Result<(RocksdbOptions, RocksdbOptions), String> {
-    let mut base_file = File::open(&base).expect("config open failed");
-    let mut s = String::new();
-    base_file.read_to_string(&mut s).expect("config read failed");
-    let base_cfg =
-        toml::Value::Table(toml::Parser::new(&s).parse().expect("malformed config file"));
+// Merge the config files in order, then apply the "key=value" overrides.
+pub fn load_config(files: &[String], overrides: &[String]) -> Result<toml::Value, String> {
+    let mut config = toml::Value::Table(toml::Table::new());
+    for file in files {
+        merge_toml(&mut config, try!(load_toml(file)));
+    }
+    for o in overrides {
+        let (key, value) = try!(parse_override(o));
+        try!(set_toml_value(&mut config, &key, value));
+    }
+    Ok(config)
+}
+
+pub struct DbConfig {
+    pub db_opts: RocksdbOptions,
+    pub cf_opts: RocksdbOptions,
+    // Every option read from the config, with the defaults filled in.
+    pub resolved: toml::Value,
+    pub shared_cache: Option<Cache>,
+    pub prefix_extractor: Option<PrefixExtractor>,
+    pub info_log_dir: Option<String>,
+}
+
+impl DbConfig {
+    // Call right before opening the db: creating the info log may roll the LOG of a live
+    // db, so reading, checking or hashing a config must not do it.
+    pub fn create_info_log(&mut self) -> Result<(), String> {
+        match self.info_log_dir {
+            Some(ref dir) => {
+                self.db_opts
+                    .create_info_log(dir)
+                    .map_err(|e| format!("create the info log in {} failed: {}", dir, e))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// The closest known key, if it is close enough to be a typo, or the known key with
+// the same name under another table, eg: "rocksdb.cf.block-size" for
+// "rocksdb.defaultcf.block-size".
+fn suggest(key: &str, known: &BTreeSet<String>) -> Option<String> {
+    let closest = known.iter()
+        .map(|k| (edit_distance(key, k), k))
+        .min()
+        .and_then(|(d, k)| if d * 3 <= key.len() { Some(k) } else { None });
+    let name = key.rsplit('.').next().unwrap();
+    closest.or_else(|| known.iter().find(|k| k.rsplit('.').next() == Some(name))).cloned()
+}
+
+fn unknown_keys(config: &toml::Value, known: &BTreeSet<String>) -> Vec<UnknownKey> {
+    let mut keys = vec![];
+    toml_leaf_keys("", config, &mut keys);
+    keys.into_iter()
+        .filter(|k| !known.contains(k))
+        .map(|k| {
+            UnknownKey {
+                suggestion: suggest(&k, known),
+                key: k,
+            }
+        })
+        .collect()
+}
 
+// Every key of the config must map to a known option, unless `lenient`, which only
+// warns about the unknown ones.
+pub fn get_db_config(config: &toml::Value, lenient: bool) -> Result<DbConfig, ConfigErrors> {
+    let mut cfg = ConfigReader::new(config);
+    let db_opts = get_rocksdb_db_option(&mut cfg);
+    let info_log_dir = cfg.get_string("rocksdb.info-log-dir", Some("".to_owned()));
+    let shared_cache = get_shared_block_cache(&mut cfg);
     let default_values = CfOptValues::default();
-    let opt_db = get_rocksdb_db_option(&base_cfg);
-    let opt_cf = get_rocksdb_cf_option(&base_cfg, "rocksdb.cf", default_values);
-    Ok((opt_db, opt_cf))
+    let prefix_extractor = get_prefix_extractor(&mut cfg, "cf", &default_values);
+    let cf_opts = get_rocksdb_cf_option(&mut cfg,
+                                        "cf",
+                                        default_values,
+                                        shared_cache.as_ref(),
+                                        prefix_extractor);
+    let mut errors = ConfigErrors {
+        invalid: cfg.errors,
+        unknown: unknown_keys(config, &cfg.known),
+    };
+    if lenient {
+        for k in errors.unknown.drain(..) {
+            println!("warning: {}", k);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(DbConfig {
+        db_opts: db_opts,
+        cf_opts: cf_opts,
+        resolved: cfg.resolved,
+        shared_cache: shared_cache,
+        prefix_extractor: prefix_extractor,
+        info_log_dir: if info_log_dir.is_empty() { None } else { Some(info_log_dir) },
+    })
+}
+
+// Print our view of the options, and the OPTIONS file RocksDB wrote if there is one.
+pub fn print_db_config(resolved: &toml::Value, db_path: &str) {
+    println!("resolved config:");
+    print!("{}", resolved);
+    if let Some(path) = options::latest_options_file(db_path) {
+        if let Some(s) = options::read_latest_options_file(db_path) {
+            println!("rocksdb options ({}):", path.display());
+            print!("{}", s);
+        }
+    }
 }
@@ -11,7 +11,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::process;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
 use toml;
 
 const UNIT: usize = 1;
@@ -30,12 +32,6 @@ const SECOND: usize = MS * TIME_MAGNITUDE_1;
 const MINTUE: usize = SECOND * TIME_MAGNITUDE_2;
 const HOUR: usize = MINTUE * TIME_MAGNITUDE_2;
 
-// TODO: remove this, do not exit. read with error return
-fn exit_with_err(msg: String) -> ! {
-    print!("{}", msg);
-    process::exit(1)
-}
-
 fn split_property(property: &str) -> Result<(f64, &str), String> {
     let mut indx = 0;
     for s in property.chars() {
@@ -74,45 +70,306 @@ pub fn parse_readable_int(size: &str) -> Result<i64, String> {
     }
 }
 
-pub fn get_toml_boolean(config: &toml::Value, name: &str, default: Option<bool>) -> bool {
-    let b = match config.lookup(name) {
-        Some(&toml::Value::Boolean(b)) => b,
-        None => {
-            default.unwrap_or_else(|| exit_with_err(format!("please specify {}", name)))
+pub fn load_toml(path: &str) -> Result<toml::Value, String> {
+    let mut s = String::new();
+    try!(File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut s))
+        .map_err(|e| format!("read {} failed: {}", path, e)));
+    let mut parser = toml::Parser::new(&s);
+    match parser.parse() {
+        Some(table) => Ok(toml::Value::Table(table)),
+        None => Err(format!("malformed toml {}: {:?}", path, parser.errors)),
+    }
+}
+
+// A bad value in the config: the key path, what was expected there and what was
+// found, `value` being None if the key is missing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+    pub key: String,
+    pub expected: String,
+    pub value: Option<String>,
+}
+
+impl ConfigError {
+    pub fn new(key: &str, expected: &str, value: Option<&toml::Value>) -> ConfigError {
+        ConfigError {
+            key: key.to_owned(),
+            expected: expected.to_owned(),
+            value: value.map(|v| v.to_string()),
         }
-        _ => exit_with_err(format!("{} boolean is excepted", name)),
-    };
+    }
+}
 
-    b
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value {
+            Some(ref v) => write!(f, "{}: {} is expected, got {}", self.key, self.expected, v),
+            None => write!(f, "{}: please specify, {} is expected", self.key, self.expected),
+        }
+    }
+}
+
+impl From<ConfigError> for String {
+    fn from(e: ConfigError) -> String {
+        e.to_string()
+    }
 }
 
-pub fn get_toml_string(config: &toml::Value, name: &str, default: Option<String>) -> String {
-    let s = match config.lookup(name) {
-        Some(&toml::Value::String(ref s)) => s.clone(),
-        None => {
-            default.unwrap_or_else(|| exit_with_err(format!("please specify {}", name)))
+// A key in the config that maps to no option, with the closest known key if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownKey {
+    pub key: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}: unknown option", self.key));
+        if let Some(ref s) = self.suggestion {
+            try!(write!(f, ", did you mean {}?", s));
         }
-        _ => exit_with_err(format!("{} string is excepted", name)),
-    };
-    s
+        Ok(())
+    }
+}
+
+// All the bad values and unknown keys found in one pass over the config.
+#[derive(Debug, Default)]
+pub struct ConfigErrors {
+    pub invalid: Vec<ConfigError>,
+    pub unknown: Vec<UnknownKey>,
+}
+
+impl ConfigErrors {
+    pub fn is_empty(&self) -> bool {
+        self.invalid.is_empty() && self.unknown.is_empty()
+    }
+}
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{} config errors:", self.invalid.len() + self.unknown.len()));
+        for e in &self.invalid {
+            try!(write!(f, "\n  {}", e));
+        }
+        for e in &self.unknown {
+            try!(write!(f, "\n  {}", e));
+        }
+        Ok(())
+    }
+}
+
+impl From<ConfigErrors> for String {
+    fn from(e: ConfigErrors) -> String {
+        e.to_string()
+    }
+}
+
+const BOOLEAN_EXPECTED: &'static str = "boolean";
+const STRING_EXPECTED: &'static str = "string";
+const INT_EXPECTED: &'static str = "int or readable int, eg: 64MB";
+const FLOAT_EXPECTED: &'static str = "float";
+
+pub fn get_toml_boolean(config: &toml::Value,
+                        name: &str,
+                        default: Option<bool>)
+                        -> Result<bool, ConfigError> {
+    match config.lookup(name) {
+        Some(&toml::Value::Boolean(b)) => Ok(b),
+        None => default.ok_or_else(|| ConfigError::new(name, BOOLEAN_EXPECTED, None)),
+        v => Err(ConfigError::new(name, BOOLEAN_EXPECTED, v)),
+    }
+}
+
+pub fn get_toml_string(config: &toml::Value,
+                       name: &str,
+                       default: Option<String>)
+                       -> Result<String, ConfigError> {
+    match config.lookup(name) {
+        Some(&toml::Value::String(ref s)) => Ok(s.clone()),
+        None => default.ok_or_else(|| ConfigError::new(name, STRING_EXPECTED, None)),
+        v => Err(ConfigError::new(name, STRING_EXPECTED, v)),
+    }
+}
+
+pub fn get_toml_float(config: &toml::Value,
+                      name: &str,
+                      default: Option<f64>)
+                      -> Result<f64, ConfigError> {
+    match config.lookup(name) {
+        Some(&toml::Value::Float(f)) => Ok(f),
+        Some(&toml::Value::Integer(i)) => Ok(i as f64),
+        None => default.ok_or_else(|| ConfigError::new(name, FLOAT_EXPECTED, None)),
+        v => Err(ConfigError::new(name, FLOAT_EXPECTED, v)),
+    }
+}
+
+pub fn get_toml_int_opt(config: &toml::Value, name: &str) -> Result<Option<i64>, ConfigError> {
+    match config.lookup(name) {
+        Some(&toml::Value::Integer(i)) => Ok(Some(i)),
+        Some(v @ &toml::Value::String(_)) => {
+            parse_readable_int(v.as_str().unwrap())
+                .map(Some)
+                .map_err(|_| ConfigError::new(name, INT_EXPECTED, Some(v)))
+        }
+        None => Ok(None),
+        v => Err(ConfigError::new(name, INT_EXPECTED, v)),
+    }
+}
+
+pub fn get_toml_int(config: &toml::Value,
+                    name: &str,
+                    default: Option<i64>)
+                    -> Result<i64, ConfigError> {
+    match try!(get_toml_int_opt(config, name)) {
+        Some(i) => Ok(i),
+        None => default.ok_or_else(|| ConfigError::new(name, INT_EXPECTED, None)),
+    }
+}
+
+// Every leaf key path of `value` under `prefix`, eg: "rocksdb.cf.block-size".
+pub fn toml_leaf_keys(prefix: &str, value: &toml::Value, keys: &mut Vec<String>) {
+    match *value {
+        toml::Value::Table(ref t) => {
+            for (k, v) in t {
+                let path = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                toml_leaf_keys(&path, v, keys);
+            }
+        }
+        _ => keys.push(prefix.to_owned()),
+    }
+}
+
+// Levenshtein distance, in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let sub = prev[j] + if ca == cb { 0 } else { 1 };
+            curr[j + 1] = sub.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+// Set `value` at the dotted `path`, creating the missing tables on the way.
+pub fn set_toml_value(config: &mut toml::Value,
+                      path: &str,
+                      value: toml::Value)
+                      -> Result<(), String> {
+    let mut cur = config;
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = try!(keys.pop().ok_or_else(|| format!("bad key path: {}", path)));
+    for key in keys {
+        cur = match *cur {
+            toml::Value::Table(ref mut t) => {
+                t.entry(key.to_owned())
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            }
+            _ => return Err(format!("can not set {}, it goes through a non-table value", path)),
+        };
+    }
+    match *cur {
+        toml::Value::Table(ref mut t) => {
+            t.insert(last.to_owned(), value);
+            Ok(())
+        }
+        _ => Err(format!("can not set {}, it goes through a non-table value", path)),
+    }
 }
 
-pub fn get_toml_int_opt(config: &toml::Value, name: &str) -> Option<i64> {
-    let res = match config.lookup(name) {
-        Some(&toml::Value::Integer(i)) => Some(i),
-        Some(&toml::Value::String(ref s)) => {
-            Some(parse_readable_int(s)
-                .unwrap_or_else(|e| exit_with_err(format!("{} parse failed {:?}", name, e))))
+// Merge `overlay` into `base`: tables are merged key by key, other values replaced.
+pub fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (&mut toml::Value::Table(ref mut base), toml::Value::Table(overlay)) => {
+            for (key, v) in overlay {
+                match base.get_mut(&key) {
+                    Some(b) => {
+                        merge_toml(b, v);
+                        continue;
+                    }
+                    None => {}
+                }
+                base.insert(key, v);
+            }
         }
-        None => None,
-        _ => exit_with_err(format!("{} int or readable int is excepted", name)),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Parse "rocksdb.defaultcf.block-size=16KB" into a key path and a value. The value
+// is read as toml if it is valid toml, as a plain string otherwise.
+pub fn parse_override(s: &str) -> Result<(String, toml::Value), String> {
+    let mut parts = s.splitn(2, '=');
+    let key = parts.next().unwrap().trim();
+    let raw = match parts.next() {
+        Some(raw) if !key.is_empty() => raw.trim(),
+        _ => return Err(format!("{} is not in form of key=value", s)),
+    };
+    let value = match toml::Parser::new(&format!("v = {}", raw)).parse() {
+        Some(mut t) => t.remove("v").unwrap(),
+        None => toml::Value::String(raw.to_owned()),
     };
-    res
+    Ok((key.to_owned(), value))
 }
 
-pub fn get_toml_int(config: &toml::Value, name: &str, default: Option<i64>) -> i64 {
-    get_toml_int_opt(config, name).unwrap_or_else(|| {
-        let i = default.unwrap_or_else(|| exit_with_err(format!("please specify {}", name)));
-        i
-    })
+#[cfg(test)]
+mod test {
+    use toml;
+    use super::{edit_distance, get_toml_boolean, get_toml_int, merge_toml, parse_override,
+                set_toml_value};
+
+    #[test]
+    fn test_layered_config() {
+        let mut base: toml::Value = "[rocksdb]\nmax-open-files = 1\n\
+                                     [rocksdb.cf]\nblock-size = \"64KB\""
+            .parse()
+            .unwrap();
+        let overlay: toml::Value = "[rocksdb.cf]\nblock-size = \"16KB\"".parse().unwrap();
+        merge_toml(&mut base, overlay);
+        assert_eq!(base.lookup("rocksdb.cf.block-size").unwrap().as_str(), Some("16KB"));
+        assert_eq!(base.lookup("rocksdb.max-open-files").unwrap().as_integer(), Some(1));
+
+        let (key, value) = parse_override("rocksdb.cf.block-size=4KB").unwrap();
+        set_toml_value(&mut base, &key, value).unwrap();
+        assert_eq!(base.lookup("rocksdb.cf.block-size").unwrap().as_str(), Some("4KB"));
+        let (_, value) = parse_override("rocksdb.max-open-files = 10").unwrap();
+        assert_eq!(value.as_integer(), Some(10));
+        let (_, value) = parse_override("rocksdb.wal-dir=\"\"").unwrap();
+        assert_eq!(value.as_str(), Some(""));
+        assert!(parse_override("=1").is_err());
+        assert!(parse_override("rocksdb.wal-dir").is_err());
+    }
+
+    #[test]
+    fn test_config_error() {
+        let config: toml::Value = "[rocksdb]\nmax-open-files = \"many\"\nenable-statistics = 1"
+            .parse()
+            .unwrap();
+        assert_eq!(get_toml_int(&config, "rocksdb.max-open-files", Some(1))
+                       .unwrap_err()
+                       .to_string(),
+                   "rocksdb.max-open-files: int or readable int, eg: 64MB is expected, \
+                    got \"many\"");
+        let e = get_toml_boolean(&config, "rocksdb.enable-statistics", None).unwrap_err();
+        assert_eq!(e.value, Some("1".to_owned()));
+        let e = get_toml_int(&config, "rocksdb.wal-ttl-seconds", None).unwrap_err();
+        assert_eq!(e.value, None);
+        assert_eq!(get_toml_int(&config, "rocksdb.wal-ttl-seconds", Some(3)), Ok(3));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("block-size", "block-size"), 0);
+        assert_eq!(edit_distance("compaction-stlye", "compaction-style"), 2);
+        assert_eq!(edit_distance("rocksdb.defaultcf.block-size", "rocksdb.cf.block-size"), 7);
+    }
 }
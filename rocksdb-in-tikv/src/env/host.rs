@@ -0,0 +1,161 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// What the host looks like under a path: the mount, filesystem and block device.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    let mut s = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut s)) {
+        Ok(_) => Some(s.trim().to_owned()),
+        Err(_) => None,
+    }
+}
+
+// The option in brackets, eg: "always [madvise] never" gives "madvise".
+pub fn selected(s: &str) -> Option<&str> {
+    s.split_whitespace()
+        .find(|w| w.starts_with('[') && w.ends_with(']'))
+        .map(|w| &w[1..w.len() - 1])
+}
+
+// `path` itself if it exists, or its closest existing ancestor, the db dir may not
+// be created yet.
+pub fn existing_path(path: &str) -> Option<PathBuf> {
+    let mut p = Path::new(path).to_path_buf();
+    if p.is_relative() {
+        p = match ::std::env::current_dir() {
+            Ok(dir) => dir.join(p),
+            Err(_) => return None,
+        };
+    }
+    loop {
+        if let Ok(p) = fs::canonicalize(&p) {
+            return Some(p);
+        }
+        if !p.pop() {
+            return None;
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mount {
+    pub device: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub options: Vec<String>,
+}
+
+fn parse_mounts(s: &str) -> Vec<Mount> {
+    s.lines()
+        .filter_map(|l| {
+            let fields: Vec<&str> = l.split_whitespace().collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some(Mount {
+                device: fields[0].to_owned(),
+                // Spaces are escaped as "\040".
+                mount_point: fields[1].replace("\\040", " "),
+                fs_type: fields[2].to_owned(),
+                options: fields[3].split(',').map(|o| o.to_owned()).collect(),
+            })
+        })
+        .collect()
+}
+
+// The mount holding `path`, the one with the longest mount point above it.
+pub fn mount_of(path: &str) -> Option<Mount> {
+    let path = match existing_path(path) {
+        Some(p) => p,
+        None => return None,
+    };
+    let mounts = parse_mounts(&read_trimmed("/proc/mounts").unwrap_or_default());
+    mounts.into_iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.len())
+}
+
+// The block device under sysfs, eg: "sda" for "/dev/sda1", "nvme0n1" for
+// "/dev/nvme0n1p2". Partitions are resolved to their disk.
+pub fn block_device(device: &str) -> Option<String> {
+    let dev = match fs::canonicalize(device) {
+        Ok(dev) => dev,
+        Err(_) => return None,
+    };
+    let name = match dev.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return None,
+    };
+    let sys = Path::new("/sys/class/block").join(&name);
+    if !sys.exists() {
+        return None;
+    }
+    if sys.join("partition").exists() {
+        return fs::canonicalize(&sys)
+            .ok()
+            .and_then(|p| p.parent().and_then(|p| p.file_name()).map(|n| n.to_owned()))
+            .map(|n| n.to_string_lossy().into_owned());
+    }
+    Some(name)
+}
+
+pub fn block_queue_param(disk: &str, param: &str) -> Option<String> {
+    read_trimmed(Path::new("/sys/block").join(disk).join("queue").join(param))
+}
+
+// Bytes available to unprivileged users on the filesystem holding `path`.
+#[cfg(unix)]
+pub fn available_bytes(path: &str) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem;
+    use libc;
+
+    let path = match existing_path(path)
+        .and_then(|p| CString::new(p.to_string_lossy().into_owned()).ok()) {
+        Some(p) => p,
+        None => return None,
+    };
+    unsafe {
+        let mut stat: libc::statvfs = mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+#[cfg(not(unix))]
+pub fn available_bytes(_: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_mounts, selected};
+
+    #[test]
+    fn test_parse_host_info() {
+        let mounts = parse_mounts("/dev/sda1 / ext4 rw,noatime,nodelalloc 0 0\n\
+                                   /dev/nvme0n1 /data\\040disk xfs rw 0 0\n");
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].options, vec!["rw", "noatime", "nodelalloc"]);
+        assert_eq!(mounts[1].mount_point, "/data disk");
+        assert_eq!(selected("always [madvise] never"), Some("madvise"));
+        assert_eq!(selected("none"), None);
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// What produced a result: the host, the disk under the db and the build.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+
+use super::{host, options};
+
+pub const BIN_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+// Set by the Makefile.
+const GIT_REV: Option<&'static str> = option_env!("BUILD_GIT_REV");
+const RUST_ROCKSDB_REV: Option<&'static str> = option_env!("BUILD_RUST_ROCKSDB_REV");
+
+pub struct Metadata {
+    pub kernel: Option<String>,
+    pub cpu_model: Option<String>,
+    pub cpu_count: usize,
+    pub mem_bytes: Option<u64>,
+    pub disk: Option<String>,
+    pub disk_model: Option<String>,
+    pub disk_rotational: Option<bool>,
+    pub filesystem: Option<String>,
+    pub version: &'static str,
+    pub git_rev: Option<&'static str>,
+    pub rust_rocksdb_rev: Option<&'static str>,
+    pub features: Vec<&'static str>,
+    pub rocksdb_version: Option<String>,
+}
+
+fn features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "portable") {
+        features.push("portable");
+    }
+    if cfg!(feature = "sse") {
+        features.push("sse");
+    }
+    if cfg!(feature = "static-link") {
+        features.push("static-link");
+    }
+    features
+}
+
+// eg: "MemTotal:        6158152 kB" in /proc/meminfo.
+fn parse_mem_total(meminfo: &str) -> Option<u64> {
+    meminfo.lines()
+        .find(|l| l.starts_with("MemTotal:"))
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+impl Metadata {
+    // The RocksDB version is read from the OPTIONS file, so collect after the db is
+    // opened.
+    pub fn collect(db_path: &str) -> Metadata {
+        let cpuinfo = host::read_trimmed("/proc/cpuinfo").unwrap_or_default();
+        let mount = host::mount_of(db_path);
+        let disk = mount.as_ref().and_then(|m| host::block_device(&m.device));
+        Metadata {
+            kernel: host::read_trimmed("/proc/sys/kernel/osrelease"),
+            cpu_model: cpuinfo.lines()
+                .find(|l| l.starts_with("model name"))
+                .and_then(|l| l.splitn(2, ':').nth(1))
+                .map(|m| m.trim().to_owned()),
+            cpu_count: cpuinfo.lines().filter(|l| l.starts_with("processor")).count(),
+            mem_bytes: host::read_trimmed("/proc/meminfo").and_then(|s| parse_mem_total(&s)),
+            disk_model: disk.as_ref()
+                .and_then(|d| host::read_trimmed(format!("/sys/block/{}/device/model", d))),
+            disk_rotational: disk.as_ref()
+                .and_then(|d| host::block_queue_param(d, "rotational"))
+                .map(|r| r == "1"),
+            disk: disk,
+            filesystem: mount.map(|m| m.fs_type),
+            version: BIN_VERSION,
+            git_rev: GIT_REV,
+            rust_rocksdb_rev: RUST_ROCKSDB_REV,
+            features: features(),
+            rocksdb_version: options::rocksdb_version(db_path),
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut host = BTreeMap::new();
+        let mut build = BTreeMap::new();
+        {
+            let mut put = |t: &mut BTreeMap<String, toml::Value>, k: &str, v: Option<String>| {
+                if let Some(v) = v {
+                    t.insert(k.to_owned(), toml::Value::String(v));
+                }
+            };
+            put(&mut host, "kernel", self.kernel.clone());
+            put(&mut host, "cpu-model", self.cpu_model.clone());
+            put(&mut host, "disk", self.disk.clone());
+            put(&mut host, "disk-model", self.disk_model.clone());
+            put(&mut host, "filesystem", self.filesystem.clone());
+            put(&mut build, "version", Some(self.version.to_owned()));
+            put(&mut build, "git-rev", self.git_rev.map(|r| r.to_owned()));
+            put(&mut build, "rust-rocksdb-rev", self.rust_rocksdb_rev.map(|r| r.to_owned()));
+            put(&mut build, "rocksdb-version", self.rocksdb_version.clone());
+        }
+        host.insert("cpu-count".to_owned(), toml::Value::Integer(self.cpu_count as i64));
+        if let Some(bytes) = self.mem_bytes {
+            host.insert("mem-bytes".to_owned(), toml::Value::Integer(bytes as i64));
+        }
+        if let Some(rotational) = self.disk_rotational {
+            host.insert("disk-rotational".to_owned(), toml::Value::Boolean(rotational));
+        }
+        build.insert("features".to_owned(),
+                     toml::Value::Array(self.features
+                         .iter()
+                         .map(|f| toml::Value::String((*f).to_owned()))
+                         .collect()));
+
+        let mut table = BTreeMap::new();
+        table.insert("host".to_owned(), toml::Value::Table(host));
+        table.insert("build".to_owned(), toml::Value::Table(build));
+        toml::Value::Table(table)
+    }
+}
+
+fn or_unknown<T: fmt::Display>(v: &Option<T>) -> String {
+    v.as_ref().map_or("unknown".to_owned(), |v| v.to_string())
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f,
+                      "host: kernel {}, cpu {} x {}, mem {} bytes",
+                      or_unknown(&self.kernel),
+                      self.cpu_count,
+                      or_unknown(&self.cpu_model),
+                      or_unknown(&self.mem_bytes)));
+        try!(writeln!(f,
+                      "disk: {} {}, rotational {}, filesystem {}",
+                      or_unknown(&self.disk),
+                      or_unknown(&self.disk_model),
+                      or_unknown(&self.disk_rotational),
+                      or_unknown(&self.filesystem)));
+        writeln!(f,
+                 "build: {} git {}, rust-rocksdb {}, rocksdb {}, features [{}]",
+                 self.version,
+                 or_unknown(&self.git_rev),
+                 or_unknown(&self.rust_rocksdb_rev),
+                 or_unknown(&self.rocksdb_version),
+                 self.features.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_mem_total;
+
+    #[test]
+    fn test_parse_mem_total() {
+        assert_eq!(parse_mem_total("MemTotal:        6158152 kB\nMemFree:  1 kB"),
+                   Some(6158152 * 1024));
+        assert_eq!(parse_mem_total("MemFree:  1 kB"), None);
+    }
+}
@@ -15,3 +15,7 @@ pub mod helper;
 pub mod check;
 pub mod kgcfg;
 pub mod dbcfg;
+pub mod options;
+pub mod host;
+pub mod meta;
+pub mod prefix;
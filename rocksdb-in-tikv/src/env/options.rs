@@ -0,0 +1,66 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// RocksDB writes the options it actually runs with into `OPTIONS-<number>` files
+// in the db dir, the one with the largest number is the latest.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+
+const OPTIONS_FILE_PREFIX: &'static str = "OPTIONS-";
+const ROCKSDB_VERSION_KEY: &'static str = "rocksdb_version=";
+
+pub fn latest_options_file(db_path: &str) -> Option<PathBuf> {
+    let entries = match fs::read_dir(db_path) {
+        Ok(entries) => entries,
+        Err(_) => return None,
+    };
+    let mut latest: Option<(u64, PathBuf)> = None;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(OPTIONS_FILE_PREFIX) {
+            continue;
+        }
+        // Skip the temporary "OPTIONS-N.dbtmp" files.
+        let n = match name[OPTIONS_FILE_PREFIX.len()..].parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        if latest.as_ref().map_or(true, |&(m, _)| n > m) {
+            latest = Some((n, entry.path()));
+        }
+    }
+    latest.map(|(_, path)| path)
+}
+
+pub fn read_latest_options_file(db_path: &str) -> Option<String> {
+    let path = match latest_options_file(db_path) {
+        Some(path) => path,
+        None => return None,
+    };
+    let mut s = String::new();
+    match File::open(&path).and_then(|mut f| f.read_to_string(&mut s)) {
+        Ok(_) => Some(s),
+        Err(_) => None,
+    }
+}
+
+pub fn rocksdb_version(db_path: &str) -> Option<String> {
+    read_latest_options_file(db_path).and_then(|s| {
+        s.lines()
+            .map(|l| l.trim())
+            .find(|l| l.starts_with(ROCKSDB_VERSION_KEY))
+            .map(|l| l[ROCKSDB_VERSION_KEY.len()..].to_owned())
+    })
+}
@@ -0,0 +1,89 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Prefix extractors, the way TiKV cuts keys into prefixes for the prefix bloom
+// filters and prefix seeks.
+
+use std::cmp;
+use rocksdb::SliceTransform;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrefixExtractor {
+    // The first `len` bytes, keys shorter than that are out of the domain.
+    Fixed(usize),
+    // At most the first `len` bytes.
+    Capped(usize),
+}
+
+impl PrefixExtractor {
+    // eg: ("fixed", 8), "none" for no extractor.
+    pub fn parse(tp: &str, len: usize) -> Result<Option<PrefixExtractor>, String> {
+        match &*tp.to_lowercase() {
+            "none" => Ok(None),
+            "fixed" => Ok(Some(PrefixExtractor::Fixed(len))),
+            "capped" => Ok(Some(PrefixExtractor::Capped(len))),
+            _ => Err(format!("not valid prefix extractor: {}", tp)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PrefixExtractor::Fixed(_) => "FixedPrefixTransform",
+            PrefixExtractor::Capped(_) => "CappedPrefixTransform",
+        }
+    }
+
+    // The prefix of `key`, None if `key` is out of the domain.
+    pub fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            PrefixExtractor::Fixed(len) if key.len() >= len => Some(&key[..len]),
+            PrefixExtractor::Fixed(_) => None,
+            PrefixExtractor::Capped(len) => Some(&key[..cmp::min(len, key.len())]),
+        }
+    }
+
+    pub fn transform(&self) -> Box<SliceTransform> {
+        Box::new(Transform { extractor: *self })
+    }
+}
+
+struct Transform {
+    extractor: PrefixExtractor,
+}
+
+impl SliceTransform for Transform {
+    fn transform<'a>(&mut self, key: &'a [u8]) -> &'a [u8] {
+        self.extractor.prefix(key).unwrap_or(key)
+    }
+
+    fn in_domain(&mut self, key: &[u8]) -> bool {
+        self.extractor.prefix(key).is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PrefixExtractor;
+
+    #[test]
+    fn test_prefix_extractor() {
+        let fixed = PrefixExtractor::parse("fixed", 4).unwrap().unwrap();
+        assert_eq!(fixed.prefix(b"abcdef"), Some(&b"abcd"[..]));
+        assert_eq!(fixed.prefix(b"abc"), None);
+        let capped = PrefixExtractor::parse("Capped", 4).unwrap().unwrap();
+        assert_eq!(capped.prefix(b"abcdef"), Some(&b"abcd"[..]));
+        assert_eq!(capped.prefix(b"abc"), Some(&b"abc"[..]));
+        assert_eq!(PrefixExtractor::parse("none", 4), Ok(None));
+        assert!(PrefixExtractor::parse("hash", 4).is_err());
+    }
+}
@@ -18,53 +18,78 @@ extern crate toml;
 extern crate rand;
 
 use std::process;
-use std::time::Instant;
-use std::boxed::Box;
+use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{Arg, App, SubCommand};
-use rocksdb::DB;
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
 
 mod sim;
 mod env;
-use env::dbcfg;
-use sim::key::{KeyGen, RepeatKeyGen, IncreaseKeyGen, RandomKeyGen};
-use sim::val::ConstValGen;
-use sim::cf::{cf_default_w, cf_lock_w, cf_write_w, cf_raft_w};
+mod stats;
+mod bench;
+mod plan;
+use bench::{BenchOpts, Workload};
+use bench::dataset::Phase;
+use bench::checkpoint::{self, RestoreBy};
+use bench::settle::{Settle, SettleMode};
+use bench::crash::CrashOpts;
+use env::check::CheckResult;
+use plan::Plan;
+use plan::store;
+use env::check::Status;
+use stats::Report;
 
 const DEFAULT_KEY_LEN: usize = 32;
 const DEFAULT_VALUE_LEN: usize = 128;
 const DEFAULT_BATCH_SIZE: usize = 128;
+// The crash test writer is killed long before writing this many.
+const DEFAULT_CRASH_COUNT: usize = 1 << 40;
 
-const ROCKSDB_DB_STATS_KEY: &'static str = "rocksdb.dbstats";
-const ROCKSDB_CF_STATS_KEY: &'static str = "rocksdb.cfstats";
-
-fn run() -> Result<usize, String> {
+fn run() -> Result<(), String> {
     let app = App::new("Rocksdb in TiKV")
         .author("PingCAP")
         .about("Benchmark of rocksdb in the sim-tikv-env")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("skip_sys_check")
             .short("N")
             .takes_value(false)
             .help("skip system check")
             .required(false))
+        .arg(Arg::with_name("check_threshold")
+            .long("check-threshold")
+            .takes_value(true)
+            .possible_values(&["warn", "fail"])
+            .default_value("fail")
+            .help("abort if any system check is at or above this status")
+            .required(false))
         .arg(Arg::with_name("db_path")
             .short("d")
             .long("db")
             .takes_value(true)
             .help("rocksdb path")
-            .required(true))
+            .required_unless("print_config"))
         .arg(Arg::with_name("config")
             .short("c")
             .long("config")
             .takes_value(true)
-            .help("toml config file")
+            .multiple(true)
+            .number_of_values(1)
+            .help("toml config file, later ones are merged over the former ones")
             .required(true))
+        .arg(Arg::with_name("set")
+            .short("s")
+            .long("set")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("override a config value, eg: rocksdb.cf.block-size=16KB")
+            .required(false))
         .arg(Arg::with_name("count")
             .short("n")
             .long("count")
             .takes_value(true)
             .help("request count")
-            .required(true))
+            .required_unless("print_config"))
         .arg(Arg::with_name("key_len")
             .short("K")
             .long("key_len")
@@ -90,128 +115,503 @@ fn run() -> Result<usize, String> {
             .help("key generator, [repeat, increase, random]")
             .default_value("random")
             .required(false))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("seed of the key and value generators")
+            .default_value("0")
+            .required(false))
+        .arg(Arg::with_name("phase")
+            .long("phase")
+            .takes_value(true)
+            .possible_values(&["load", "run"])
+            .help("load: write a dataset into a new db and record it there, \
+                   run: reuse a loaded db, the keys must be generated as they were loaded")
+            .required(false))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .takes_value(true)
+            .help("take a checkpoint of the db into this dir after the run")
+            .required(false))
+        .arg(Arg::with_name("restore")
+            .long("restore")
+            .takes_value(true)
+            .help("restore the db from this checkpoint before the run, the db must not exist")
+            .required(false))
+        .arg(Arg::with_name("restore_by")
+            .long("restore-by")
+            .takes_value(true)
+            .possible_values(&["hardlink", "copy"])
+            .default_value("hardlink")
+            .help("hardlink the SST files of the checkpoint, or copy them")
+            .required(false))
+        .arg(Arg::with_name("settle")
+            .long("settle")
+            .takes_value(true)
+            .possible_values(&["sleep", "wait", "compact"])
+            .help("before the run, sleep only, or flush and wait for the compactions, or \
+                   flush and compact fully, then sleep for --settle-sleep")
+            .required(false))
+        .arg(Arg::with_name("settle_pending_bytes")
+            .long("settle-pending-bytes")
+            .takes_value(true)
+            .help("wait until the pending compaction bytes are at most this, eg: 64MB")
+            .default_value("0")
+            .required(false))
+        .arg(Arg::with_name("settle_timeout")
+            .long("settle-timeout")
+            .takes_value(true)
+            .help("stop waiting for the compactions after N seconds")
+            .default_value("3600")
+            .required(false))
+        .arg(Arg::with_name("settle_sleep")
+            .long("settle-sleep")
+            .takes_value(true)
+            .help("sleep N seconds after settling")
+            .default_value("0")
+            .required(false))
+        .arg(Arg::with_name("stats_interval")
+            .short("I")
+            .long("stats_interval")
+            .takes_value(true)
+            .help("print statistics every N seconds")
+            .required(false))
+        .arg(Arg::with_name("lenient")
+            .long("lenient")
+            .takes_value(false)
+            .help("warn about unknown config keys instead of failing")
+            .required(false))
+        .arg(Arg::with_name("print_config")
+            .long("print-config")
+            .takes_value(false)
+            .help("print the resolved config, and the rocksdb OPTIONS file of an existing \
+                   db if -d is given, then exit without running")
+            .required(false))
+        .arg(Arg::with_name("report")
+            .short("o")
+            .long("report")
+            .takes_value(true)
+            .help("write structured results to this toml file")
+            .required(false))
         .subcommand(SubCommand::with_name("cf")
             .subcommand(SubCommand::with_name("default"))
             .subcommand(SubCommand::with_name("lock"))
             .subcommand(SubCommand::with_name("write"))
             .subcommand(SubCommand::with_name("raft")))
-        .subcommand(SubCommand::with_name("txn"));
+        .subcommand(SubCommand::with_name("txn"))
+        .subcommand(SubCommand::with_name("read")
+            .about("point get the keys written by a former run with the same key_gen and seed"))
+        .subcommand(SubCommand::with_name("scan")
+            .about("seek to the keys and read batch_size entries from each, in the \
+                    prefix of the key if a prefix extractor is set"))
+        .subcommand(SubCommand::with_name("verify")
+            .about("regenerate the keys and value written by a former run and check them, \
+                    as recorded by the load and later run phases if the db has a dataset")
+            .arg(Arg::with_name("workload")
+                .long("workload")
+                .takes_value(true)
+                .help("the workload that wrote the db, eg: \"cf lock\" deletes every key")
+                .default_value("cf default")
+                .required(false)))
+        .subcommand(SubCommand::with_name("crash")
+            .about("kill a writer with SIGKILL, reopen the db with each wal recovery mode and \
+                    check every acknowledged write is there")
+            .arg(Arg::with_name("modes")
+                .long("modes")
+                .takes_value(true)
+                .help("wal recovery modes to test")
+                .default_value("0,1,2,3")
+                .required(false))
+            .arg(Arg::with_name("rounds")
+                .long("rounds")
+                .takes_value(true)
+                .help("crashes per recovery mode")
+                .default_value("3")
+                .required(false))
+            .arg(Arg::with_name("kill_within")
+                .long("kill-within")
+                .takes_value(true)
+                .help("kill the writer at random in N ms after its first write")
+                .default_value("3000")
+                .required(false))
+            .arg(Arg::with_name("sync")
+                .long("sync")
+                .takes_value(true)
+                .help("sync of the writes to test each mode with, eg: false")
+                .default_value("false,true")
+                .required(false)))
+        .subcommand(SubCommand::with_name(bench::crash::WRITER_SUBCOMMAND)
+            .setting(AppSettings::Hidden)
+            .arg(Arg::with_name("sync")
+                .long("sync")
+                .required(false)))
+        .subcommand(SubCommand::with_name("plan")
+            .about("run the jobs of a toml plan and compare their results")
+            .arg(Arg::with_name("plan")
+                .takes_value(true)
+                .help("toml plan file")
+                .required(true))
+            .arg(Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .takes_value(true)
+                .help("write structured results of every job into this dir")
+                .required(false))
+            .arg(Arg::with_name("results")
+                .short("r")
+                .long("results")
+                .takes_value(true)
+                .help("store the results by plan, config hash and version into this dir")
+                .required(false)))
+        .subcommand(SubCommand::with_name("check")
+            .about("run every system check and print the results")
+            .arg(Arg::with_name("db_path")
+                .short("d")
+                .long("db")
+                .takes_value(true)
+                .help("check the disk and filesystem of this rocksdb path")
+                .required(false))
+            .arg(Arg::with_name("dataset_size")
+                .long("dataset-size")
+                .takes_value(true)
+                .help("check the free space for a dataset of this size, eg: 100GB")
+                .required(false))
+            .arg(Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .takes_value(true)
+                .help("write the results to this toml file")
+                .required(false))
+            .arg(Arg::with_name("threshold")
+                .long("threshold")
+                .takes_value(true)
+                .possible_values(&["warn", "fail"])
+                .default_value("fail")
+                .help("exit with error if any check is at or above this status")
+                .required(false)))
+        .subcommand(SubCommand::with_name("config")
+            .subcommand(SubCommand::with_name("check")
+                .about("validate toml config files without running anything")
+                .arg(Arg::with_name("config")
+                    .takes_value(true)
+                    .multiple(true)
+                    .help("toml config files, later ones are merged over the former ones")
+                    .required(true))
+                .arg(Arg::with_name("set")
+                    .short("s")
+                    .long("set")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("override a config value, eg: rocksdb.cf.block-size=16KB")
+                    .required(false))
+                .arg(Arg::with_name("lenient")
+                    .long("lenient")
+                    .takes_value(false)
+                    .help("warn about unknown config keys instead of failing")
+                    .required(false))))
+        .subcommand(SubCommand::with_name("compare")
+            .about("compare stored results with a baseline, fail on regressions")
+            .arg(Arg::with_name("results")
+                .takes_value(true)
+                .help("dir of the stored results")
+                .required(true))
+            .arg(Arg::with_name("plan")
+                .short("p")
+                .long("plan")
+                .takes_value(true)
+                .help("plan name")
+                .required(true))
+            .arg(Arg::with_name("baseline")
+                .short("b")
+                .long("baseline")
+                .takes_value(true)
+                .help("baseline version, eg: 0.1.0-rocksdb-5.4.6")
+                .required(true))
+            .arg(Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .takes_value(true)
+                .help("version to check, the latest stored one by default")
+                .required(false))
+            .arg(Arg::with_name("threshold")
+                .long("threshold")
+                .takes_value(true)
+                .help("percentage of tps drop or latency rise taken as a regression")
+                .default_value("5")
+                .required(false)));
 
     let matches = app.clone().get_matches();
 
-    if !matches.is_present("skip_sys_check") {
-        if let Err(e) = env::check::check_system_config() {
-            return Err(format!("system config not satisfied: {}\n", e));
-        }
+    // Nothing runs with these, so the system check is not needed.
+    if matches.is_present("print_config") {
+        return bench::print_config(&values(&matches, "config"),
+                                   &values(&matches, "set"),
+                                   matches.is_present("lenient"),
+                                   matches.value_of("db_path"));
+    }
+    if let ("config", Some(config)) = matches.subcommand() {
+        return match config.subcommand() {
+            ("check", Some(check)) => run_config_check(check),
+            _ => help_err(app),
+        };
     }
 
-    let db_path = matches.value_of("db_path").unwrap();
-    let cfg = matches.value_of("config").unwrap();
-    let (opt_db, opt_cf) = try!(dbcfg::get_db_config(cfg));
-    let db = try!(DB::open_cf(opt_db, db_path, &["default"], &[&opt_cf]));
+    if let ("check", Some(check)) = matches.subcommand() {
+        return run_check(check);
+    }
 
-    let count = match matches.value_of("count") {
-        Some(v) => {
-            match v.parse() {
-                Ok(v) => v,
-                Err(count) => return Err(format!("{} is not a number", count)),
-            }
+    let mut checks = None;
+    if !matches.is_present("skip_sys_check") {
+        // Checked against the device of the db, and the size of the data to write.
+        let kv_len = parse_num(&matches, "key_len", Some(DEFAULT_KEY_LEN)).unwrap_or(0) +
+                     parse_num(&matches, "val_len", Some(DEFAULT_VALUE_LEN)).unwrap_or(0);
+        let dataset_bytes = parse_num::<u64>(&matches, "count", None)
+            .ok()
+            .map(|count| count * kv_len as u64);
+        let threshold = try!(Status::parse(matches.value_of("check_threshold").unwrap()));
+        match env::check::check_system_config(matches.value_of("db_path"),
+                                              dataset_bytes,
+                                              threshold) {
+            Ok(results) => checks = Some(results),
+            Err(e) => return Err(format!("system config not satisfied:\n{}\n", e)),
         }
-        None => DEFAULT_KEY_LEN,
-    };
-    let key_len = match matches.value_of("key_len") {
-        Some(v) => {
-            match v.parse() {
-                Ok(v) => v,
-                Err(key_len) => return Err(format!("{} is not a number", key_len)),
+    }
+
+    let workload = match matches.subcommand() {
+        ("cf", Some(cf)) => {
+            match cf.subcommand_name() {
+                Some(name) => try!(Workload::parse(&format!("cf {}", name))),
+                None => return help_err(app),
             }
         }
-        None => DEFAULT_VALUE_LEN,
-    };
-    let val_len = match matches.value_of("val_len") {
-        Some(v) => {
-            match v.parse() {
-                Ok(v) => v,
-                Err(val_len) => return Err(format!("{} is not a number", val_len)),
-            }
+        ("txn", _) => Workload::Txn,
+        ("read", _) => Workload::Read,
+        ("scan", _) => Workload::Scan,
+        ("verify", Some(verify)) => try!(Workload::parse(verify.value_of("workload").unwrap())),
+        ("crash", Some(crash)) => return run_crash(&matches, crash, checks),
+        (bench::crash::WRITER_SUBCOMMAND, Some(writer)) => {
+            return bench::crash::write_until_killed(&try!(crash_opts(&matches)),
+                                                    writer.is_present("sync"))
         }
-        None => DEFAULT_VALUE_LEN,
-    };
-    let batch_size = match matches.value_of("batch_size") {
-        Some(v) => {
-            match v.parse() {
-                Ok(v) => v,
-                Err(batch_size) => return Err(format!("{} is not a number", batch_size)),
-            }
-        } 
-        None => DEFAULT_BATCH_SIZE,
+        ("plan", Some(plan)) => return run_plan(plan),
+        ("compare", Some(compare)) => return run_compare(compare),
+        _ => return help_err(app),
     };
 
-    let mut key_gen: Box<KeyGen> = match matches.value_of("key_gen").unwrap() {
-        "repeat" => Box::new(RepeatKeyGen::new(key_len, count)),
-        "increase" => Box::new(IncreaseKeyGen::new(key_len, count)),
-        "random" => Box::new(RandomKeyGen::new(key_len, count)),
-        invalid => return Err(format!("{} is not a valid key_gen", invalid)),
+    let mut opts = BenchOpts {
+        db_path: try!(required(&matches, "db_path")).to_owned(),
+        configs: try!(required(&matches, "config").map(|_| values(&matches, "config"))),
+        overrides: values(&matches, "set"),
+        count: try!(parse_num(&matches, "count", None)),
+        key_len: try!(parse_num(&matches, "key_len", Some(DEFAULT_KEY_LEN))),
+        val_len: try!(parse_num(&matches, "val_len", Some(DEFAULT_VALUE_LEN))),
+        batch_size: try!(parse_num(&matches, "batch_size", Some(DEFAULT_BATCH_SIZE))),
+        key_gen: matches.value_of("key_gen").unwrap().to_owned(),
+        seed: try!(parse_num(&matches, "seed", None)),
+        workload: workload,
+        phase: match matches.value_of("phase") {
+            Some(phase) => Some(try!(Phase::parse(phase))),
+            None => None,
+        },
+        checkpoint: matches.value_of("checkpoint").map(|s| s.to_owned()),
+        settle: None,
+        stats_interval: None,
+        lenient: matches.is_present("lenient"),
     };
-    let mut val_gen = ConstValGen::new(val_len);
+    if let Some(mode) = matches.value_of("settle") {
+        let pending = matches.value_of("settle_pending_bytes").unwrap();
+        opts.settle = Some(Settle {
+            mode: try!(SettleMode::parse(mode)),
+            max_pending_bytes: try!(env::helper::parse_readable_int(pending)
+                .map_err(|_| format!("{} is not a size", pending))) as u64,
+            timeout: Duration::from_secs(try!(parse_num(&matches, "settle_timeout", None))),
+            sleep: Duration::from_secs(try!(parse_num(&matches, "settle_sleep", None))),
+        });
+    }
+    if matches.is_present("stats_interval") {
+        let secs = try!(parse_num(&matches, "stats_interval", None));
+        opts.stats_interval = Some(Duration::from_secs(secs));
+    }
+    if let Some(dir) = matches.value_of("restore") {
+        let by = try!(RestoreBy::parse(matches.value_of("restore_by").unwrap()));
+        try!(checkpoint::restore(dir, &opts.db_path, by));
+    }
+    if matches.subcommand_name() == Some("verify") {
+        return run_verify(&matches, &opts, checks);
+    }
+    let result = try!(bench::run(&opts));
 
-    let res = match matches.subcommand() {
-        ("cf", Some(cf)) => {
-            match cf.subcommand_name().unwrap() {
-                "default" => cf_default_w(&db, &mut *key_gen, &mut val_gen, batch_size),
-                "lock" => cf_lock_w(&db, &mut *key_gen, &mut val_gen, batch_size),
-                "write" => cf_write_w(&db, &mut *key_gen, &mut val_gen, batch_size),
-                "raft" => cf_raft_w(&db, &mut *key_gen, &mut val_gen, batch_size),
-                _ => help_err(app),
-            }
-        }
-        ("txn", _) => {
-            return Err("txn bench mark not impl".to_owned());
+    if let Some(path) = matches.value_of("report") {
+        let mut report = result.to_report();
+        if let Some(ref checks) = checks {
+            report.insert("checks", env::check::checks_to_toml(checks));
         }
-        _ => help_err(app),
-    };
+        try!(report.save(path));
+    }
+    Ok(())
+}
 
-    output_stats(&db);
+fn run_plan(matches: &ArgMatches) -> Result<(), String> {
+    let plan = try!(Plan::load(matches.value_of("plan").unwrap()));
+    let summaries = try!(plan.run(matches.value_of("out")));
+    plan::print_results(&summaries);
+    if let Some(dir) = matches.value_of("results") {
+        try!(store::save(dir, &plan.name, &summaries));
+    }
+    Ok(())
+}
 
-    match res {
-        Ok(_) => Ok(count),
-        Err(e) => Err(e),
+fn run_verify(matches: &ArgMatches,
+              opts: &BenchOpts,
+              checks: Option<Vec<CheckResult>>)
+              -> Result<(), String> {
+    let result = try!(bench::verify::verify(opts));
+    if let Some(path) = matches.value_of("report") {
+        let mut report = Report::new();
+        report.insert("verify", result.to_toml());
+        if let Some(ref checks) = checks {
+            report.insert("checks", env::check::checks_to_toml(checks));
+        }
+        try!(report.save(path));
+    }
+    if !result.ok() {
+        return Err("verify failed".to_owned());
     }
+    Ok(())
 }
 
-fn help_err(app: clap::App) -> Result<(), String> {
-    let mut help = Vec::new();
-    app.write_help(&mut help).unwrap();
-    Err(String::from_utf8(help).unwrap())
+// The writer and the verifier of the crash test share these.
+fn crash_opts(matches: &ArgMatches) -> Result<CrashOpts, String> {
+    Ok(CrashOpts {
+        db_path: try!(required(matches, "db_path")).to_owned(),
+        configs: try!(required(matches, "config").map(|_| values(matches, "config"))),
+        overrides: values(matches, "set"),
+        lenient: matches.is_present("lenient"),
+        count: try!(parse_num(matches, "count", Some(DEFAULT_CRASH_COUNT))),
+        key_len: try!(parse_num(matches, "key_len", Some(DEFAULT_KEY_LEN))),
+        val_len: try!(parse_num(matches, "val_len", Some(DEFAULT_VALUE_LEN))),
+        batch_size: try!(parse_num(matches, "batch_size", Some(DEFAULT_BATCH_SIZE))),
+        modes: vec![],
+        syncs: vec![],
+        rounds: 0,
+        kill_within: Duration::from_secs(0),
+    })
 }
 
-fn output_stats(db: &DB) {
-    if let Some(db_stats) = db.get_property_value(ROCKSDB_DB_STATS_KEY) {
-        print!("{}", db_stats);
+fn run_crash(matches: &ArgMatches,
+             crash: &ArgMatches,
+             checks: Option<Vec<CheckResult>>)
+             -> Result<(), String> {
+    let mut opts = try!(crash_opts(matches));
+    for mode in crash.value_of("modes").unwrap().split(',') {
+        opts.modes.push(try!(mode.trim()
+            .parse()
+            .map_err(|_| format!("{} is not a recovery mode", mode))));
     }
-    for name in db.cf_names() {
-        let handler = db.cf_handle(name).expect("");
-        if let Some(cf_stats) = db.get_property_value_cf(handler, ROCKSDB_CF_STATS_KEY) {
-            print!("{}", cf_stats);
+    for sync in crash.value_of("sync").unwrap().split(',') {
+        opts.syncs.push(try!(sync.trim()
+            .parse()
+            .map_err(|_| format!("{} is not a sync, true or false", sync))));
+    }
+    opts.rounds = try!(parse_num(crash, "rounds", None));
+    opts.kill_within = Duration::from_millis(try!(parse_num(crash, "kill_within", None)));
+    let results = try!(bench::crash::run(&opts));
+    bench::crash::print_results(&results);
+
+    if let Some(path) = matches.value_of("report") {
+        let mut report = Report::new();
+        report.insert("crash",
+                      toml::Value::Array(results.iter().map(|r| r.to_toml()).collect()));
+        if let Some(ref checks) = checks {
+            report.insert("checks", env::check::checks_to_toml(checks));
         }
+        try!(report.save(path));
     }
+    Ok(())
 }
 
-fn main() {
-    let timer = Instant::now();
-    match run() {
-        Err(e) => {
-            println!("{}", e);
-            process::exit(1)
-        }
-        Ok(count) => {
-            let elapsed = timer.elapsed();
-            let tps = count as f64 /
-                      (elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9);
-            println!("invoke {} times in {} ms, tps: {}",
-                     count,
-                     elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() as f64 / 1e6) as u64,
-                     tps as u64);
+fn run_check(matches: &ArgMatches) -> Result<(), String> {
+    let dataset_bytes = match matches.value_of("dataset_size") {
+        Some(size) => {
+            let bytes = try!(env::helper::parse_readable_int(size)
+                .map_err(|_| format!("{} is not a size", size)));
+            Some(bytes as u64)
         }
+        None => None,
     };
+    let results = env::check::check_system(matches.value_of("db_path"), dataset_bytes);
+    env::check::print_checks(&results);
+    if let Some(path) = matches.value_of("out") {
+        let mut report = Report::new();
+        report.insert("checks", env::check::checks_to_toml(&results));
+        try!(report.save(path));
+    }
+    let threshold = try!(Status::parse(matches.value_of("threshold").unwrap()));
+    let failed = env::check::failed(&results, threshold);
+    if !failed.is_empty() {
+        return Err(format!("{} of {} checks at or above {}",
+                           failed.len(),
+                           results.len(),
+                           threshold.name()));
+    }
+    Ok(())
+}
+
+fn run_config_check(matches: &ArgMatches) -> Result<(), String> {
+    let configs = values(matches, "config");
+    let config = try!(env::dbcfg::load_config(&configs, &values(matches, "set")));
+    let db_cfg = try!(env::dbcfg::get_db_config(&config, matches.is_present("lenient")));
+    print!("{}", db_cfg.resolved);
+    println!("config ok: {}", configs.join(", "));
+    Ok(())
+}
+
+fn run_compare(matches: &ArgMatches) -> Result<(), String> {
+    let threshold: f64 = try!(parse_num(matches, "threshold", None));
+    let (comparisons, missing) = try!(store::compare(matches.value_of("results").unwrap(),
+                                                     matches.value_of("plan").unwrap(),
+                                                     matches.value_of("baseline").unwrap(),
+                                                     matches.value_of("target")));
+    let failures = store::print_comparisons(&comparisons, &missing, threshold);
+    if failures > 0 {
+        return Err(format!("{} of {} configs regressed beyond {}% or miss a result",
+                           failures,
+                           comparisons.len() + missing.len(),
+                           threshold));
+    }
+    Ok(())
+}
+
+// `SubcommandsNegateReqs` lifts the `required` args as soon as a subcommand is given,
+// so the bench checks them here.
+fn required<'a>(matches: &'a ArgMatches, name: &str) -> Result<&'a str, String> {
+    matches.value_of(name).ok_or_else(|| format!("please specify {}", name))
+}
+
+fn values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches.values_of(name).map_or(vec![], |vs| vs.map(|v| v.to_owned()).collect())
+}
+
+fn parse_num<T: FromStr>(matches: &ArgMatches,
+                         name: &str,
+                         default: Option<T>)
+                         -> Result<T, String> {
+    match matches.value_of(name) {
+        Some(v) => v.parse().map_err(|_| format!("{} is not a number", v)),
+        None => default.ok_or_else(|| format!("please specify {}", name)),
+    }
+}
+
+fn help_err<T>(app: clap::App) -> Result<T, String> {
+    let mut help = Vec::new();
+    app.write_help(&mut help).unwrap();
+    Err(String::from_utf8(help).unwrap())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+        process::exit(1)
+    }
 }
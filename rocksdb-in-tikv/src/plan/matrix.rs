@@ -0,0 +1,135 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use toml;
+
+// One point of a matrix: a value for each of the varied config keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combination {
+    pub values: Vec<(String, toml::Value)>,
+}
+
+fn fmt_value(v: &toml::Value) -> String {
+    match *v {
+        toml::Value::String(ref s) => s.clone(),
+        ref v => v.to_string(),
+    }
+}
+
+impl Combination {
+    // eg: "compaction-priority=0,block-size=16KB"
+    pub fn label(&self) -> String {
+        let items: Vec<String> = self.values
+            .iter()
+            .map(|&(ref key, ref v)| {
+                let short = key.rsplit('.').next().unwrap_or(key);
+                format!("{}={}", short, fmt_value(v))
+            })
+            .collect();
+        items.join(",")
+    }
+
+    // Whether every key in `filter` has the same value in this combination.
+    pub fn matches(&self, filter: &toml::Table) -> bool {
+        filter.iter().all(|(key, v)| self.values.iter().any(|&(ref k, ref x)| k == key && x == v))
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        toml::Value::Table(self.values.iter().cloned().collect())
+    }
+}
+
+fn filters(plan: &toml::Value, key: &str) -> Result<Vec<toml::Table>, String> {
+    match plan.lookup(key) {
+        Some(&toml::Value::Array(ref items)) => {
+            items.iter()
+                .map(|item| match *item {
+                    toml::Value::Table(ref t) => Ok(t.clone()),
+                    _ => Err(format!("{} should be an array of tables", key)),
+                })
+                .collect()
+        }
+        None => Ok(vec![]),
+        _ => Err(format!("{} should be an array of tables", key)),
+    }
+}
+
+// Expand `[matrix]` into its cartesian product, then keep the combinations matching
+// one of `matrix-only` (if any) and none of `matrix-exclude`.
+pub fn expand(plan: &toml::Value) -> Result<Vec<Combination>, String> {
+    let matrix = match plan.lookup("matrix") {
+        Some(&toml::Value::Table(ref t)) => t,
+        None => return Ok(vec![]),
+        _ => return Err("matrix should be a table".to_owned()),
+    };
+
+    let mut combos = vec![Combination { values: vec![] }];
+    for (key, values) in matrix {
+        let values = match *values {
+            toml::Value::Array(ref values) if !values.is_empty() => values,
+            _ => return Err(format!("matrix.{} should be a non-empty array", key)),
+        };
+        let mut next = Vec::with_capacity(combos.len() * values.len());
+        for combo in &combos {
+            for v in values {
+                let mut c = combo.clone();
+                c.values.push((key.clone(), v.clone()));
+                next.push(c);
+            }
+        }
+        combos = next;
+    }
+
+    let only = try!(filters(plan, "matrix-only"));
+    let exclude = try!(filters(plan, "matrix-exclude"));
+    Ok(combos.into_iter()
+        .filter(|c| only.is_empty() || only.iter().any(|f| c.matches(f)))
+        .filter(|c| !exclude.iter().any(|f| c.matches(f)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use toml;
+    use super::expand;
+
+    #[test]
+    fn test_expand_matrix() {
+        let plan: toml::Value = r#"
+            [matrix]
+            "rocksdb.cf.compaction-priority" = [0, 1, 2, 3]
+            "rocksdb.cf.block-size" = ["16KB", "64KB"]
+
+            [[matrix-exclude]]
+            "rocksdb.cf.compaction-priority" = 2
+        "#
+            .parse()
+            .unwrap();
+        let combos = expand(&plan).unwrap();
+        assert_eq!(combos.len(), 6);
+        assert_eq!(combos[0].label(), "block-size=16KB,compaction-priority=0");
+        assert_eq!(combos[5].label(), "block-size=64KB,compaction-priority=3");
+
+        let plan: toml::Value = r#"
+            [matrix]
+            a = [1, 2]
+            b = [1, 2]
+
+            [[matrix-only]]
+            a = 1
+        "#
+            .parse()
+            .unwrap();
+        assert_eq!(expand(&plan).unwrap().len(), 2);
+    }
+}
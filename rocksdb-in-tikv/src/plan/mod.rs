@@ -0,0 +1,603 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod matrix;
+pub mod summary;
+pub mod store;
+
+use std::fs;
+use std::cmp::Ordering;
+use std::path::Path;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use toml;
+use rand::{Rng, SeedableRng, XorShiftRng, thread_rng};
+
+use bench::{self, BenchOpts, BenchResult, Workload};
+use bench::dataset::Phase;
+use bench::checkpoint::{self, RestoreBy};
+use bench::settle::{Settle, SettleMode};
+use bench::verify::{self, VerifyResult};
+use env::dbcfg;
+use env::helper::{load_toml, parse_readable_int};
+use env::meta::BIN_VERSION;
+use stats::Report;
+use self::matrix::Combination;
+use self::summary::Summary;
+
+const DEFAULT_DB_PREFIX: &'static str = "rocksdb_test";
+
+// Phases run in this order, a plan picks the ones it needs with `[phase.<name>]`.
+const PHASES: &'static [&'static str] = &["load", "warmup", "bench", "verify"];
+
+const BENCH_PHASE: &'static str = "bench";
+const LOAD_PHASE: &'static str = "load";
+const VERIFY_PHASE: &'static str = "verify";
+
+// Values are looked up from the innermost table to the outermost: phase, job, plan.
+struct Scope<'a> {
+    tables: Vec<&'a toml::Value>,
+}
+
+impl<'a> Scope<'a> {
+    fn get(&self, key: &str) -> Option<&'a toml::Value> {
+        self.tables.iter().filter_map(|t| t.as_table().and_then(|t| t.get(key))).next()
+    }
+
+    fn get_string(&self, key: &str) -> Result<String, String> {
+        match self.get(key) {
+            Some(&toml::Value::String(ref s)) => Ok(s.clone()),
+            None => Err(format!("please specify {}", key)),
+            _ => Err(format!("{} string is excepted", key)),
+        }
+    }
+
+    // A string or an array of strings.
+    fn get_strings(&self, key: &str) -> Result<Vec<String>, String> {
+        let err = || format!("{} string or array of strings is excepted", key);
+        match self.get(key) {
+            Some(&toml::Value::String(ref s)) => Ok(vec![s.clone()]),
+            Some(&toml::Value::Array(ref items)) => {
+                items.iter().map(|v| v.as_str().map(|s| s.to_owned()).ok_or_else(&err)).collect()
+            }
+            None => Ok(vec![]),
+            _ => Err(err()),
+        }
+    }
+
+    // `settle` is the mode, the timeout and sleep are in seconds.
+    fn get_settle(&self) -> Result<Option<Settle>, String> {
+        if self.get("settle").is_none() {
+            return Ok(None);
+        }
+        let secs = |key, default| {
+            self.get_size(key, default).map(|s| Duration::from_secs(s as u64))
+        };
+        Ok(Some(Settle {
+            mode: try!(SettleMode::parse(&try!(self.get_string("settle")))),
+            max_pending_bytes: try!(self.get_size("settle-pending-bytes", 0)) as u64,
+            timeout: try!(secs("settle-timeout", 3600)),
+            sleep: try!(secs("settle-sleep", 0)),
+        }))
+    }
+
+    // An int or a readable int, eg: "64MB".
+    fn get_size(&self, key: &str, default: usize) -> Result<usize, String> {
+        match self.get(key) {
+            Some(&toml::Value::String(ref s)) => {
+                parse_readable_int(s)
+                    .ok()
+                    .and_then(|i| if i >= 0 { Some(i as usize) } else { None })
+                    .ok_or_else(|| format!("{} non-negative readable int is excepted", key))
+            }
+            Some(_) => self.get_usize(key),
+            None => Ok(default),
+        }
+    }
+
+    fn get_usize(&self, key: &str) -> Result<usize, String> {
+        match self.get(key) {
+            Some(&toml::Value::Integer(i)) if i >= 0 => Ok(i as usize),
+            None => Err(format!("please specify {}", key)),
+            _ => Err(format!("{} non-negative int is excepted", key)),
+        }
+    }
+}
+
+// Result of one trial of a job.
+pub struct JobResult {
+    pub trial: usize,
+    pub config: String,
+    pub config_hash: String,
+    pub phases: Vec<(&'static str, BenchResult)>,
+    pub verify: Option<VerifyResult>,
+}
+
+impl JobResult {
+    pub fn bench(&self) -> Option<&BenchResult> {
+        self.phases.iter().find(|&&(name, _)| name == BENCH_PHASE).map(|&(_, ref r)| r)
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut phases = BTreeMap::new();
+        for &(name, ref result) in &self.phases {
+            phases.insert(name.to_owned(), result.to_report().to_toml());
+        }
+        let mut table = BTreeMap::new();
+        table.insert("trial".to_owned(), toml::Value::Integer(self.trial as i64));
+        table.insert("config".to_owned(), toml::Value::String(self.config.clone()));
+        table.insert("phase".to_owned(), toml::Value::Table(phases));
+        if let Some(ref v) = self.verify {
+            table.insert("verify".to_owned(), v.to_toml());
+        }
+        toml::Value::Table(table)
+    }
+}
+
+// All trials of a job, summarized over their bench phases.
+pub struct JobSummary {
+    pub name: String,
+    pub config: String,
+    pub config_hash: String,
+    pub rocksdb_version: Option<String>,
+    // Host and build of the first trial.
+    pub meta: Option<toml::Value>,
+    pub combination: Option<Combination>,
+    pub trials: Vec<JobResult>,
+    pub tps: Summary,
+    pub p99: Summary,
+    pub write_amp: Summary,
+    pub stall_ms: Summary,
+}
+
+impl JobSummary {
+    fn new(job: &Job, trials: Vec<JobResult>) -> JobSummary {
+        let benches: Vec<&BenchResult> = trials.iter().filter_map(|t| t.bench()).collect();
+        let tps: Vec<f64> = benches.iter().map(|b| b.tps()).collect();
+        let p99: Vec<f64> = benches.iter().filter_map(|b| b.p99()).collect();
+        let write_amp: Vec<f64> = benches.iter().filter_map(|b| b.amp.write_amp()).collect();
+        let stall_ms: Vec<f64> =
+            benches.iter().map(|b| b.samples.stalls.total_ms() as f64).collect();
+        JobSummary {
+            name: job.name.clone(),
+            config: trials.first().map_or(String::new(), |t| t.config.clone()),
+            config_hash: trials.first().map_or(String::new(), |t| t.config_hash.clone()),
+            rocksdb_version: benches.iter().filter_map(|b| b.rocksdb_version.clone()).next(),
+            meta: benches.first().map(|b| b.meta.to_toml()),
+            combination: job.combination.clone(),
+            tps: Summary::of(&tps),
+            p99: Summary::of(&p99),
+            write_amp: Summary::of(&write_amp),
+            stall_ms: Summary::of(&stall_ms),
+            trials: trials,
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        table.insert("name".to_owned(), toml::Value::String(self.name.clone()));
+        table.insert("config".to_owned(), toml::Value::String(self.config.clone()));
+        table.insert("config-hash".to_owned(),
+                     toml::Value::String(self.config_hash.clone()));
+        table.insert("version".to_owned(),
+                     toml::Value::String(BIN_VERSION.to_owned()));
+        if let Some(ref v) = self.rocksdb_version {
+            table.insert("rocksdb-version".to_owned(), toml::Value::String(v.clone()));
+        }
+        if let Some(ref c) = self.combination {
+            table.insert("matrix".to_owned(), c.to_toml());
+        }
+        if let Some(ref meta) = self.meta {
+            table.insert("meta".to_owned(), meta.clone());
+        }
+        table.insert("tps".to_owned(), self.tps.to_toml());
+        table.insert("p99".to_owned(), self.p99.to_toml());
+        table.insert("write-amp".to_owned(), self.write_amp.to_toml());
+        table.insert("stall-ms".to_owned(), self.stall_ms.to_toml());
+        table.insert("trial".to_owned(),
+                     toml::Value::Array(self.trials.iter().map(|t| t.to_toml()).collect()));
+        toml::Value::Table(table)
+    }
+}
+
+struct Job {
+    name: String,
+    table: toml::Value,
+    combination: Option<Combination>,
+}
+
+pub struct Plan {
+    pub name: String,
+    root: toml::Value,
+    phases: Vec<&'static str>,
+    jobs: Vec<Job>,
+}
+
+impl Plan {
+    pub fn load(path: &str) -> Result<Plan, String> {
+        let root = try!(load_toml(path));
+
+        let phases = match root.lookup("phase") {
+            Some(&toml::Value::Table(ref t)) => {
+                for name in t.keys() {
+                    if !PHASES.contains(&name.as_str()) {
+                        return Err(format!("unknown phase {}, expect one of {:?}", name, PHASES));
+                    }
+                }
+                PHASES.iter().cloned().filter(|p| t.contains_key(*p)).collect()
+            }
+            _ => return Err(format!("plan {} has no [phase.*] tables", path)),
+        };
+        let tables = match root.lookup("job") {
+            Some(&toml::Value::Array(ref jobs)) => jobs.clone(),
+            _ => return Err(format!("plan {} has no [[job]] tables", path)),
+        };
+
+        // A job with a matrix, or under a plan with one, is run once per combination.
+        let mut jobs = vec![];
+        for (idx, table) in tables.into_iter().enumerate() {
+            let name = match table.lookup("name") {
+                Some(&toml::Value::String(ref s)) => s.clone(),
+                _ => format!("job-{}", idx),
+            };
+            let mut combos = try!(matrix::expand(&table));
+            if combos.is_empty() {
+                combos = try!(matrix::expand(&root));
+            }
+            if combos.is_empty() {
+                jobs.push(Job {
+                    name: name,
+                    table: table,
+                    combination: None,
+                });
+                continue;
+            }
+            for c in combos {
+                jobs.push(Job {
+                    name: format!("{}/{}", name, c.label()),
+                    table: table.clone(),
+                    combination: Some(c),
+                });
+            }
+        }
+
+        let name = match root.lookup("name") {
+            Some(&toml::Value::String(ref s)) => s.clone(),
+            _ => {
+                Path::new(path)
+                    .file_stem()
+                    .map_or("plan".to_owned(), |s| s.to_string_lossy().into_owned())
+            }
+        };
+
+        Ok(Plan {
+            name: name,
+            root: root,
+            phases: phases,
+            jobs: jobs,
+        })
+    }
+
+    fn db_path(&self, idx: usize) -> String {
+        let prefix = match self.root.lookup("db") {
+            Some(&toml::Value::String(ref s)) => s.as_str(),
+            _ => DEFAULT_DB_PREFIX,
+        };
+        format!("{}.{}", prefix, idx)
+    }
+
+    // With `checkpoint = true`, each job loads once into a checkpoint, and every trial
+    // restores from it instead of running the load phase again.
+    fn checkpoint_path(&self, idx: usize) -> Option<String> {
+        match self.root.lookup("checkpoint") {
+            Some(&toml::Value::Boolean(true)) if self.phases.contains(&LOAD_PHASE) => {
+                Some(format!("{}.checkpoint", self.db_path(idx)))
+            }
+            _ => None,
+        }
+    }
+
+    fn restore_by(&self) -> Result<RestoreBy, String> {
+        match self.root.lookup("restore-by") {
+            Some(&toml::Value::String(ref s)) => RestoreBy::parse(s),
+            None => Ok(RestoreBy::Hardlink),
+            _ => Err("restore-by string is excepted".to_owned()),
+        }
+    }
+
+    fn keep_db(&self) -> bool {
+        match self.root.lookup("keep-db") {
+            Some(&toml::Value::Boolean(b)) => b,
+            _ => false,
+        }
+    }
+
+    fn trials(&self) -> Result<usize, String> {
+        match self.root.lookup("trials") {
+            Some(&toml::Value::Integer(n)) if n > 0 => Ok(n as usize),
+            None => Ok(1),
+            _ => Err("trials positive int is excepted".to_owned()),
+        }
+    }
+
+    // Trials of all jobs are interleaved in random order by default, so that drifts of
+    // the disk or host do not favor the jobs running first.
+    fn schedule(&self) -> Result<Vec<(usize, usize)>, String> {
+        let trials = try!(self.trials());
+        let mut schedule = vec![];
+        for trial in 0..trials {
+            for idx in 0..self.jobs.len() {
+                schedule.push((idx, trial));
+            }
+        }
+        let shuffle = match self.root.lookup("shuffle") {
+            Some(&toml::Value::Boolean(b)) => b,
+            None => true,
+            _ => return Err("shuffle boolean is excepted".to_owned()),
+        };
+        if !shuffle {
+            return Ok(schedule);
+        }
+        match self.root.lookup("seed") {
+            Some(&toml::Value::Integer(seed)) => {
+                let seed = seed as u32;
+                XorShiftRng::from_seed([seed, seed ^ 0x9e3779b9, 1, 2]).shuffle(&mut schedule)
+            }
+            None => thread_rng().shuffle(&mut schedule),
+            _ => return Err("seed int is excepted".to_owned()),
+        }
+        Ok(schedule)
+    }
+
+    fn bench_opts(&self, idx: usize, phase: &str, db_path: &str) -> Result<BenchOpts, String> {
+        let phase_key = format!("phase.{}", phase);
+        let mut scope = Scope { tables: vec![] };
+        if let Some(t) = self.jobs[idx].table.lookup(&phase_key) {
+            scope.tables.push(t);
+        }
+        scope.tables.push(&self.jobs[idx].table);
+        if let Some(t) = self.root.lookup(&phase_key) {
+            scope.tables.push(t);
+        }
+        scope.tables.push(&self.root);
+
+        let configs = try!(scope.get_strings("config"));
+        if configs.is_empty() {
+            return Err("please specify config".to_owned());
+        }
+        // The matrix combination goes over the `set` overrides.
+        let mut overrides = try!(scope.get_strings("set"));
+        if let Some(ref c) = self.jobs[idx].combination {
+            overrides.extend(c.values.iter().map(|&(ref k, ref v)| format!("{}={}", k, v)));
+        }
+
+        Ok(BenchOpts {
+            db_path: db_path.to_owned(),
+            configs: configs,
+            overrides: overrides,
+            count: try!(scope.get_usize("count")),
+            key_len: try!(scope.get_usize("key-len")),
+            val_len: try!(scope.get_usize("val-len")),
+            batch_size: try!(scope.get_usize("batch-size")),
+            key_gen: try!(scope.get_string("key-gen")),
+            // "seed" is taken by the schedule.
+            seed: match scope.get("key-seed") {
+                Some(_) => try!(scope.get_usize("key-seed")) as u32,
+                None => 0,
+            },
+            workload: try!(Workload::parse(&try!(scope.get_string("workload")))),
+            // With a load phase, the later phases check that they run on its dataset.
+            phase: if phase == LOAD_PHASE {
+                Some(Phase::Load)
+            } else if self.phases.contains(&LOAD_PHASE) {
+                Some(Phase::Run)
+            } else {
+                None
+            },
+            checkpoint: None,
+            settle: try!(scope.get_settle()),
+            stats_interval: None,
+            lenient: match scope.get("lenient") {
+                Some(&toml::Value::Boolean(b)) => b,
+                None => false,
+                _ => return Err("lenient boolean is excepted".to_owned()),
+            },
+        })
+    }
+
+    // Identifies what a job runs: the effective options and resolved config of every
+    // phase, hashed as toml, whose tables are sorted by key.
+    fn config_hash(&self, idx: usize) -> Result<String, String> {
+        let mut phases = BTreeMap::new();
+        for &phase in &self.phases {
+            let opts = try!(self.bench_opts(idx, phase, ""));
+            let config = try!(dbcfg::load_config(&opts.configs, &opts.overrides));
+            let db_cfg = try!(dbcfg::get_db_config(&config, opts.lenient));
+            let mut t = BTreeMap::new();
+            t.insert("opts".to_owned(), opts.to_toml());
+            t.insert("config".to_owned(), db_cfg.resolved);
+            phases.insert(phase.to_owned(), toml::Value::Table(t));
+        }
+        let canonical = toml::Value::Table(phases).to_string();
+        Ok(format!("{:016x}", store::fnv1a(canonical.as_bytes())))
+    }
+
+    fn run_job(&self,
+               idx: usize,
+               trial: usize,
+               out_dir: Option<&str>)
+               -> Result<JobResult, String> {
+        let name = &self.jobs[idx].name;
+        let db_path = self.db_path(idx);
+        if Path::new(&db_path).exists() {
+            try!(fs::remove_dir_all(&db_path)
+                .map_err(|e| format!("remove {} failed: {}", db_path, e)));
+        }
+
+        let mut result = JobResult {
+            trial: trial,
+            config: String::new(),
+            config_hash: String::new(),
+            phases: vec![],
+            verify: None,
+        };
+        let checkpoint_path = self.checkpoint_path(idx);
+        for &phase in &self.phases {
+            let mut opts = try!(self.bench_opts(idx, phase, &db_path)
+                .map_err(|e| format!("job {} phase {}: {}", name, phase, e)));
+            let mut restore = false;
+            if let (LOAD_PHASE, Some(ref dir)) = (phase, checkpoint_path.as_ref()) {
+                if Path::new(dir).exists() {
+                    try!(checkpoint::restore(dir, &db_path, try!(self.restore_by())));
+                    continue;
+                }
+                // The first trial also starts from the checkpoint, as the later ones do.
+                opts.checkpoint = Some(dir.to_string());
+                restore = true;
+            }
+            let config = opts.configs.join(" + ");
+            println!("################ job: {}, trial: {}, phase: {}, config: {} ################",
+                     name,
+                     trial,
+                     phase,
+                     config);
+            if phase == VERIFY_PHASE {
+                let res = try!(verify::verify(&opts)
+                    .map_err(|e| format!("job {} phase {} failed: {}", name, phase, e)));
+                if let Some(dir) = out_dir {
+                    let path = Path::new(dir).join(format!("{}.{}.{}.toml", idx, trial, phase));
+                    let mut report = Report::new();
+                    report.insert("verify", res.to_toml());
+                    try!(report.save(&path.to_string_lossy()));
+                }
+                if !res.ok() {
+                    return Err(format!("job {} phase {} failed: {}", name, phase, res));
+                }
+                result.verify = Some(res);
+                continue;
+            }
+            let res = try!(bench::run(&opts)
+                .map_err(|e| format!("job {} phase {} failed: {}", name, phase, e)));
+            if let Some(dir) = out_dir {
+                let path = Path::new(dir).join(format!("{}.{}.{}.toml", idx, trial, phase));
+                try!(res.to_report().save(&path.to_string_lossy()));
+            }
+            if result.config_hash.is_empty() {
+                result.config_hash = try!(self.config_hash(idx));
+            }
+            result.config = config;
+            result.phases.push((phase, res));
+            if restore {
+                try!(fs::remove_dir_all(&db_path)
+                    .map_err(|e| format!("remove {} failed: {}", db_path, e)));
+                try!(checkpoint::restore(opts.checkpoint.as_ref().unwrap(),
+                                         &db_path,
+                                         try!(self.restore_by())));
+            }
+        }
+
+        if !self.keep_db() {
+            try!(fs::remove_dir_all(&db_path)
+                .map_err(|e| format!("remove {} failed: {}", db_path, e)));
+        }
+        Ok(result)
+    }
+
+    fn remove_checkpoints(&self) -> Result<(), String> {
+        for idx in 0..self.jobs.len() {
+            if let Some(dir) = self.checkpoint_path(idx) {
+                if Path::new(&dir).exists() {
+                    try!(fs::remove_dir_all(&dir)
+                        .map_err(|e| format!("remove {} failed: {}", dir, e)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run(&self, out_dir: Option<&str>) -> Result<Vec<JobSummary>, String> {
+        if let Some(dir) = out_dir {
+            try!(fs::create_dir_all(dir).map_err(|e| format!("create {} failed: {}", dir, e)));
+        }
+        // Checkpoints left by a former run may be of another config.
+        try!(self.remove_checkpoints());
+        let mut results: Vec<Vec<JobResult>> = self.jobs.iter().map(|_| vec![]).collect();
+        for (idx, trial) in try!(self.schedule()) {
+            let res = try!(self.run_job(idx, trial, out_dir));
+            results[idx].push(res);
+        }
+        if !self.keep_db() {
+            try!(self.remove_checkpoints());
+        }
+        let summaries: Vec<JobSummary> = self.jobs
+            .iter()
+            .zip(results.into_iter())
+            .map(|(job, mut trials)| {
+                trials.sort_by_key(|t| t.trial);
+                JobSummary::new(job, trials)
+            })
+            .collect();
+        if let Some(dir) = out_dir {
+            let mut report = Report::new();
+            report.insert("job",
+                          toml::Value::Array(summaries.iter().map(|s| s.to_toml()).collect()));
+            try!(report.save(&Path::new(dir).join("results.toml").to_string_lossy()));
+        }
+        Ok(summaries)
+    }
+}
+
+pub fn print_results(summaries: &[JobSummary]) {
+    println!("{:<32} {:<32} {:>20} {:>20} {:>10} {:>10}",
+             "job",
+             "config",
+             "tps",
+             "p99",
+             "write-amp",
+             "stall-ms");
+    for s in summaries {
+        println!("{:<32} {:<32} {:>20} {:>20} {:>10.2} {:>10.0}",
+                 s.name,
+                 s.config,
+                 s.tps.to_string(),
+                 s.p99.to_string(),
+                 s.write_amp.mean,
+                 s.stall_ms.mean);
+    }
+
+    // A NaN tps, eg: of a run taking no time, can not be ranked.
+    let mut ranked: Vec<&JobSummary> =
+        summaries.iter().filter(|s| s.tps.n > 0 && !s.tps.mean.is_nan()).collect();
+    ranked.sort_by(|a, b| b.tps.mean.partial_cmp(&a.tps.mean).unwrap_or(Ordering::Equal));
+    let best = match ranked.first() {
+        Some(best) => best,
+        None => return,
+    };
+    println!("");
+    println!("fastest: {}, tps: {}, config as: {}", best.name, best.tps, best.config);
+    let second = match ranked.get(1) {
+        Some(second) => second,
+        None => return,
+    };
+    println!("runner-up: {}, tps: {}, config as: {}",
+             second.name,
+             second.tps,
+             second.config);
+    match summary::significant(&best.tps, &second.tps) {
+        Some(true) => println!("the difference is significant at 95% confidence"),
+        Some(false) => {
+            println!("the difference is NOT significant at 95% confidence, it may be noise")
+        }
+        None => println!("run with trials >= 2 to tell whether the difference is significant"),
+    }
+}
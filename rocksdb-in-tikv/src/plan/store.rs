@@ -0,0 +1,250 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Results are stored as `<dir>/<plan>/<config-hash>/<version>.toml`, `version` being
+// the version of this binary and of RocksDB, so an upgrade can be diffed against
+// the stored baseline of every config.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml;
+
+use env::helper::load_toml;
+use env::meta::BIN_VERSION;
+use stats::Report;
+use super::JobSummary;
+use super::summary::{self, Summary};
+
+const RESULT_FILE_EXT: &'static str = "toml";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// 64-bit FNV-1a. Stored results are keyed by it, so unlike `DefaultHasher` it must not
+// change between Rust releases.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |h, &b| (h ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+pub fn version_key(rocksdb_version: Option<&str>) -> String {
+    format!("{}-rocksdb-{}", BIN_VERSION, rocksdb_version.unwrap_or("unknown"))
+}
+
+pub fn save(dir: &str, plan: &str, summaries: &[JobSummary]) -> Result<(), String> {
+    for s in summaries {
+        let job_dir = Path::new(dir).join(plan).join(&s.config_hash);
+        try!(fs::create_dir_all(&job_dir)
+            .map_err(|e| format!("create {} failed: {}", job_dir.display(), e)));
+        let version = version_key(s.rocksdb_version.as_ref().map(|v| v.as_str()));
+        let path = job_dir.join(format!("{}.{}", version, RESULT_FILE_EXT));
+        let mut report = Report::new();
+        report.insert("job", s.to_toml());
+        try!(report.save(&path.to_string_lossy()));
+        println!("result saved: {}", path.display());
+    }
+    Ok(())
+}
+
+fn summary_from_toml(job: &toml::Value, key: &str) -> Summary {
+    let table = match job.lookup(key) {
+        Some(&toml::Value::Table(ref t)) => t,
+        _ => return Summary::default(),
+    };
+    Summary {
+        n: table.get("n").and_then(|v| v.as_integer()).unwrap_or(0) as usize,
+        mean: table.get("mean").and_then(|v| v.as_float()).unwrap_or(0.0),
+        stddev: table.get("stddev").and_then(|v| v.as_float()).unwrap_or(0.0),
+    }
+}
+
+struct Stored {
+    name: String,
+    tps: Summary,
+    p99: Summary,
+}
+
+fn load_stored(path: &Path) -> Result<Stored, String> {
+    let root = try!(load_toml(&path.to_string_lossy()));
+    let job = try!(root.lookup("job")
+        .ok_or_else(|| format!("no [job] in {}", path.display())));
+    Ok(Stored {
+        name: job.lookup("name").and_then(|v| v.as_str()).unwrap_or("").to_owned(),
+        tps: summary_from_toml(job, "tps"),
+        p99: summary_from_toml(job, "p99"),
+    })
+}
+
+// The most recently written result besides the baseline.
+fn latest_result(job_dir: &Path, baseline: &Path) -> Option<PathBuf> {
+    let entries = match fs::read_dir(job_dir) {
+        Ok(entries) => entries,
+        Err(_) => return None,
+    };
+    entries.filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.as_path() != baseline)
+        .filter_map(|p| fs::metadata(&p).and_then(|m| m.modified()).ok().map(|t| (t, p)))
+        .max()
+        .map(|(_, p)| p)
+}
+
+pub struct Comparison {
+    pub name: String,
+    pub config_hash: String,
+    pub target: String,
+    pub base_tps: Summary,
+    pub tps: Summary,
+    // Of the latency histogram the workload of the job runs.
+    pub base_p99: Summary,
+    pub p99: Summary,
+}
+
+fn change(base: &Summary, target: &Summary) -> f64 {
+    if base.mean == 0.0 {
+        0.0
+    } else {
+        (target.mean - base.mean) / base.mean * 100.0
+    }
+}
+
+impl Comparison {
+    pub fn tps_change(&self) -> f64 {
+        change(&self.base_tps, &self.tps)
+    }
+
+    pub fn p99_change(&self) -> f64 {
+        change(&self.base_p99, &self.p99)
+    }
+
+    // Slower or higher latency beyond `threshold` percent. When both sides have
+    // enough trials, a change that is not significant is not a regression.
+    pub fn regressed(&self, threshold: f64) -> bool {
+        let tps = self.tps_change() < -threshold &&
+                  summary::significant(&self.base_tps, &self.tps).unwrap_or(true);
+        let p99 = self.p99_change() > threshold &&
+                  summary::significant(&self.base_p99, &self.p99).unwrap_or(true);
+        tps || p99
+    }
+}
+
+// A config stored with only one of the two versions, eg: its job failed to run.
+pub struct Missing {
+    pub name: String,
+    pub config_hash: String,
+    // The version missing, "baseline" or "target".
+    pub side: &'static str,
+}
+
+// Compare the `baseline` version of every config of `plan` with `target`, or with
+// the latest other version stored if `target` is not given. The configs missing either
+// version are returned too, as the gate can not pass them.
+pub fn compare(dir: &str,
+               plan: &str,
+               baseline: &str,
+               target: Option<&str>)
+               -> Result<(Vec<Comparison>, Vec<Missing>), String> {
+    let plan_dir = Path::new(dir).join(plan);
+    let entries = try!(fs::read_dir(&plan_dir)
+        .map_err(|e| format!("read {} failed: {}", plan_dir.display(), e)));
+    let mut comparisons = vec![];
+    let mut missing = vec![];
+    for entry in entries.filter_map(|e| e.ok()) {
+        let job_dir = entry.path();
+        let config_hash = entry.file_name().to_string_lossy().into_owned();
+        let base_path = job_dir.join(format!("{}.{}", baseline, RESULT_FILE_EXT));
+        let target_path = match target {
+            Some(t) => Some(job_dir.join(format!("{}.{}", t, RESULT_FILE_EXT))),
+            None => latest_result(&job_dir, &base_path),
+        };
+        let target_path = target_path.and_then(|p| if p.exists() { Some(p) } else { None });
+        let (base_path, target_path) = match (base_path.exists(), target_path) {
+            (true, Some(target_path)) => (base_path, target_path),
+            (false, None) => continue,
+            (true, None) => {
+                missing.push(Missing {
+                    name: try!(load_stored(&base_path)).name,
+                    config_hash: config_hash,
+                    side: "target",
+                });
+                continue;
+            }
+            (false, Some(target_path)) => {
+                missing.push(Missing {
+                    name: try!(load_stored(&target_path)).name,
+                    config_hash: config_hash,
+                    side: "baseline",
+                });
+                continue;
+            }
+        };
+        let base = try!(load_stored(&base_path));
+        let curr = try!(load_stored(&target_path));
+        comparisons.push(Comparison {
+            name: base.name,
+            config_hash: config_hash,
+            target: target_path.file_stem().unwrap().to_string_lossy().into_owned(),
+            base_tps: base.tps,
+            tps: curr.tps,
+            base_p99: base.p99,
+            p99: curr.p99,
+        });
+    }
+    if comparisons.is_empty() && missing.is_empty() {
+        return Err(format!("no results of {} to compare with baseline {}", plan, baseline));
+    }
+    comparisons.sort_by(|a, b| a.name.cmp(&b.name));
+    missing.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((comparisons, missing))
+}
+
+// Print the comparisons and the missing configs, and return how many of the configs
+// regressed or miss a version.
+pub fn print_comparisons(comparisons: &[Comparison], missing: &[Missing], threshold: f64) -> usize {
+    println!("{:<32} {:<16} {:<24} {:>10} {:>12} {:>12}",
+             "job",
+             "config-hash",
+             "target",
+             "tps",
+             "p99",
+             "regressed");
+    let mut regressions = 0;
+    for c in comparisons {
+        let regressed = c.regressed(threshold);
+        if regressed {
+            regressions += 1;
+        }
+        println!("{:<32} {:<16} {:<24} {:>+9.2}% {:>+11.2}% {:>12}",
+                 c.name,
+                 c.config_hash,
+                 c.target,
+                 c.tps_change(),
+                 c.p99_change(),
+                 regressed);
+    }
+    for m in missing {
+        println!("{:<32} {:<16} no {} result", m.name, m.config_hash, m.side);
+    }
+    regressions + missing.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::fnv1a;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+
+// Two-sided 95% critical values of Student's t distribution, by degrees of freedom.
+const T_95: [f64; 30] = [12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+                         2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+                         2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042];
+
+fn t_critical(df: f64) -> f64 {
+    let df = df.floor() as usize;
+    match df {
+        0 => ::std::f64::INFINITY,
+        1...30 => T_95[df - 1],
+        31...60 => 2.000,
+        61...120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+// Mean, sample standard deviation and 95% confidence interval of repeated trials.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Summary {
+    pub fn of(samples: &[f64]) -> Summary {
+        let n = samples.len();
+        if n == 0 {
+            return Summary::default();
+        }
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            let var = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() /
+                      (n - 1) as f64;
+            var.sqrt()
+        } else {
+            0.0
+        };
+        Summary {
+            n: n,
+            mean: mean,
+            stddev: stddev,
+        }
+    }
+
+    fn sem2(&self) -> f64 {
+        self.stddev * self.stddev / self.n as f64
+    }
+
+    // Half width of the 95% confidence interval, None with less than 2 trials.
+    pub fn ci95(&self) -> Option<f64> {
+        if self.n < 2 {
+            return None;
+        }
+        Some(t_critical((self.n - 1) as f64) * self.sem2().sqrt())
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        table.insert("n".to_owned(), toml::Value::Integer(self.n as i64));
+        table.insert("mean".to_owned(), toml::Value::Float(self.mean));
+        table.insert("stddev".to_owned(), toml::Value::Float(self.stddev));
+        if let Some(ci) = self.ci95() {
+            table.insert("ci95".to_owned(), toml::Value::Float(ci));
+        }
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ci95() {
+            Some(ci) => write!(f, "{:.2} ±{:.2}", self.mean, ci),
+            None => write!(f, "{:.2}", self.mean),
+        }
+    }
+}
+
+// Welch's t-test at 95%: whether the means of `a` and `b` differ beyond noise.
+// None if either side has less than 2 trials.
+pub fn significant(a: &Summary, b: &Summary) -> Option<bool> {
+    if a.n < 2 || b.n < 2 {
+        return None;
+    }
+    let (va, vb) = (a.sem2(), b.sem2());
+    if va + vb == 0.0 {
+        return Some(a.mean != b.mean);
+    }
+    let t = (a.mean - b.mean).abs() / (va + vb).sqrt();
+    let df = (va + vb) * (va + vb) /
+             (va * va / (a.n - 1) as f64 + vb * vb / (b.n - 1) as f64);
+    Some(t > t_critical(df))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Summary, significant};
+
+    #[test]
+    fn test_summary() {
+        let s = Summary::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(s.n, 8);
+        assert_eq!(s.mean, 5.0);
+        assert!((s.stddev - 2.138).abs() < 1e-3);
+        assert!((s.ci95().unwrap() - 1.787).abs() < 1e-3);
+        assert!(Summary::of(&[1.0]).ci95().is_none());
+    }
+
+    #[test]
+    fn test_significant() {
+        let a = Summary::of(&[100.0, 101.0, 99.0, 100.5]);
+        let b = Summary::of(&[90.0, 91.0, 89.0, 90.5]);
+        let c = Summary::of(&[95.0, 105.0, 92.0, 108.0]);
+        assert_eq!(significant(&a, &b), Some(true));
+        assert_eq!(significant(&a, &c), Some(false));
+        assert_eq!(significant(&a, &Summary::of(&[1.0])), None);
+    }
+}
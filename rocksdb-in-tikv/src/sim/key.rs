@@ -11,24 +11,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rand::{Rng, SeedableRng, XorShiftRng, thread_rng};
+use rand::{Rng, SeedableRng, XorShiftRng};
 
 pub trait KeyGen {
     fn next(&mut self) -> Option<&[u8]>;
 }
 
+// Key generators are seeded, so that a later run can regenerate the keys of a former
+// one. Seed 0 is the one used before seeds were configurable.
+fn seeded_rng(seed: u32) -> XorShiftRng {
+    XorShiftRng::from_seed([1, 1, 1, seed.wrapping_add(1)])
+}
+
 pub struct RepeatKeyGen {
     key: Vec<u8>,
     cnt: usize,
 }
 
 impl RepeatKeyGen {
-    pub fn new(len: usize, cnt: usize) -> RepeatKeyGen {
+    pub fn new(len: usize, cnt: usize, seed: u32) -> RepeatKeyGen {
         let mut keys = RepeatKeyGen {
             key: vec![0; len],
             cnt: cnt,
         };
-        thread_rng().fill_bytes(&mut keys.key);
+        seeded_rng(seed).fill_bytes(&mut keys.key);
         keys
     }
 }
@@ -87,11 +93,11 @@ pub struct RandomKeyGen {
 }
 
 impl RandomKeyGen {
-    pub fn new(len: usize, cnt: usize) -> RandomKeyGen {
+    pub fn new(len: usize, cnt: usize, seed: u32) -> RandomKeyGen {
         RandomKeyGen {
             key: vec![0; len],
             cnt: cnt,
-            rand: XorShiftRng::from_seed([1; 4]),
+            rand: seeded_rng(seed),
         }
     }
 }
@@ -114,7 +120,7 @@ mod test {
 
     #[test]
     fn test_repeate_keygen() {
-        let mut kg = RepeatKeyGen::new(8, 8);
+        let mut kg = RepeatKeyGen::new(8, 8, 0);
         while let Some(key) = kg.next() {
             println!("{:?}", key);
         }
@@ -130,9 +136,13 @@ mod test {
 
     #[test]
     fn test_random_keygen() {
-        let mut kg = RandomKeyGen::new(8, 8);
+        let mut kg = RandomKeyGen::new(8, 8, 0);
         while let Some(key) = kg.next() {
             println!("{:?}", key);
         }
+
+        let first = |seed| RandomKeyGen::new(8, 1, seed).next().map(|k| k.to_vec());
+        assert_eq!(first(7), first(7));
+        assert!(first(7) != first(8));
     }
 }
@@ -15,3 +15,4 @@ pub mod key;
 pub mod val;
 pub mod cf;
 pub mod txn;
+pub mod read;
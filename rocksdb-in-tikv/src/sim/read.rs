@@ -0,0 +1,52 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rocksdb::{DB, ReadOptions, SeekKey};
+
+use env::prefix::PrefixExtractor;
+use super::key::KeyGen;
+
+// Point gets of the keys. With use-bloom-filter they check the bloom filters by the
+// whole key, or by its prefix with a prefix extractor and no whole key filtering.
+pub fn point_r(db: &DB, keys: &mut KeyGen) -> Result<(), String> {
+    let readopts = ReadOptions::new();
+    while let Some(key) = keys.next() {
+        try!(db.get_opt(key, &readopts));
+    }
+    Ok(())
+}
+
+// Seek to every key and read up to `scan_len` entries from there. With a prefix
+// extractor a scan stays in the prefix of its key, keys out of the extractor's domain
+// fall back to a total order seek.
+pub fn scan_r(db: &DB,
+              keys: &mut KeyGen,
+              scan_len: usize,
+              extractor: Option<PrefixExtractor>)
+              -> Result<(), String> {
+    while let Some(key) = keys.next() {
+        let mut readopts = ReadOptions::new();
+        match extractor.and_then(|e| e.prefix(key)) {
+            Some(_) => readopts.set_prefix_same_as_start(true),
+            None => readopts.set_total_order_seek(true),
+        }
+        let mut iter = db.iter_opt(readopts);
+        let mut valid = iter.seek(SeekKey::Key(key));
+        let mut n = 0;
+        while valid && n < scan_len {
+            n += 1;
+            valid = iter.next();
+        }
+    }
+    Ok(())
+}
@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rand::{Rng, thread_rng};
+use rand::{Rng, SeedableRng, XorShiftRng};
 
 pub trait ValGen {
     fn next(&mut self) -> Option<&[u8]>;
@@ -22,9 +22,10 @@ pub struct ConstValGen {
 }
 
 impl ConstValGen {
-    pub fn new(len: usize) -> ConstValGen {
+    // Seeded like the key generators, so that the value can be regenerated to verify.
+    pub fn new(len: usize, seed: u32) -> ConstValGen {
         let mut vals = ConstValGen { val: vec![0; len] };
-        thread_rng().fill_bytes(&mut vals.val);
+        XorShiftRng::from_seed([2, 2, 2, seed.wrapping_add(1)]).fill_bytes(&mut vals.val);
         vals
     }
 }
@@ -41,7 +42,7 @@ mod test {
 
     #[test]
     fn test_const_valgen() {
-        let mut vg = ConstValGen::new(8);
+        let mut vg = ConstValGen::new(8, 0);
         for _ in 0..8 {
             let val = vg.next().expect("");
             println!("{:?}", val);
@@ -0,0 +1,200 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+use rocksdb::DB;
+
+use super::statistics::Statistics;
+
+const ROCKSDB_TOTAL_SST_FILES_SIZE: &'static str = "rocksdb.total-sst-files-size";
+const ROCKSDB_ESTIMATE_LIVE_DATA_SIZE: &'static str = "rocksdb.estimate-live-data-size";
+const ROCKSDB_ESTIMATE_NUM_KEYS: &'static str = "rocksdb.estimate-num-keys";
+
+const BLOCK_TICKERS: &'static [&'static str] = &["block-cache-index-hit",
+                                                 "block-cache-index-miss",
+                                                 "block-cache-filter-hit",
+                                                 "block-cache-filter-miss",
+                                                 "block-cache-data-hit",
+                                                 "block-cache-data-miss"];
+
+const BLOCK_MISS_TICKERS: &'static [&'static str] = &["block-cache-index-miss",
+                                                      "block-cache-filter-miss",
+                                                      "block-cache-data-miss"];
+
+// What a run cost, in bytes moved per user byte.
+#[derive(Default)]
+pub struct Amplification {
+    pub user_bytes: u64,
+    pub wal_bytes: u64,
+    pub flush_bytes: u64,
+    pub compaction_read_bytes: u64,
+    pub compaction_write_bytes: u64,
+    pub sst_bytes: u64,
+    // RocksDB's estimate of the SST bytes holding live data, compressed.
+    pub live_data_bytes: u64,
+    // Keys left after overwrites and deletes, and their key and value bytes.
+    pub live_keys: u64,
+    pub live_logical_bytes: u64,
+    pub keys_read: u64,
+    pub blocks_read: u64,
+    pub blocks_read_from_disk: u64,
+}
+
+fn ratio(a: u64, b: u64) -> Option<f64> {
+    if b == 0 {
+        None
+    } else {
+        Some(a as f64 / b as f64)
+    }
+}
+
+impl Amplification {
+    // `kv_len` is the length of a key plus its value.
+    pub fn collect(db: &DB, stats: &Statistics, kv_len: u64) -> Amplification {
+        let mut amp = Amplification {
+            user_bytes: stats.ticker("bytes-written"),
+            wal_bytes: stats.ticker("wal-file-bytes"),
+            flush_bytes: stats.ticker("flush-write-bytes"),
+            compaction_read_bytes: stats.ticker("compact-read-bytes"),
+            compaction_write_bytes: stats.ticker("compact-write-bytes"),
+            keys_read: stats.ticker("number-keys-read"),
+            blocks_read: BLOCK_TICKERS.iter().map(|t| stats.ticker(t)).sum(),
+            blocks_read_from_disk: BLOCK_MISS_TICKERS.iter().map(|t| stats.ticker(t)).sum(),
+            ..Default::default()
+        };
+        for name in db.cf_names() {
+            let handle = db.cf_handle(name).expect("");
+            amp.sst_bytes += db.get_property_int_cf(handle, ROCKSDB_TOTAL_SST_FILES_SIZE)
+                .unwrap_or(0);
+            amp.live_data_bytes +=
+                db.get_property_int_cf(handle, ROCKSDB_ESTIMATE_LIVE_DATA_SIZE).unwrap_or(0);
+            amp.live_keys += db.get_property_int_cf(handle, ROCKSDB_ESTIMATE_NUM_KEYS)
+                .unwrap_or(0);
+        }
+        amp.live_logical_bytes = amp.live_keys * kv_len;
+        amp
+    }
+
+    // Bytes flushed and compacted into SSTs per user byte, WAL excluded.
+    pub fn write_amp(&self) -> Option<f64> {
+        ratio(self.flush_bytes + self.compaction_write_bytes, self.user_bytes)
+    }
+
+    // Same as `write_amp`, plus the WAL.
+    pub fn total_write_amp(&self) -> Option<f64> {
+        ratio(self.wal_bytes + self.flush_bytes + self.compaction_write_bytes,
+              self.user_bytes)
+    }
+
+    // SST bytes per byte of live keys and values.
+    pub fn space_amp(&self) -> Option<f64> {
+        ratio(self.sst_bytes, self.live_logical_bytes)
+    }
+
+    pub fn blocks_per_get(&self) -> Option<f64> {
+        ratio(self.blocks_read, self.keys_read)
+    }
+
+    pub fn disk_blocks_per_get(&self) -> Option<f64> {
+        ratio(self.blocks_read_from_disk, self.keys_read)
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        let bytes = vec![("user-bytes", self.user_bytes),
+                         ("wal-bytes", self.wal_bytes),
+                         ("flush-bytes", self.flush_bytes),
+                         ("compaction-read-bytes", self.compaction_read_bytes),
+                         ("compaction-write-bytes", self.compaction_write_bytes),
+                         ("sst-bytes", self.sst_bytes),
+                         ("live-data-bytes", self.live_data_bytes),
+                         ("live-keys", self.live_keys),
+                         ("live-logical-bytes", self.live_logical_bytes),
+                         ("keys-read", self.keys_read)];
+        for (name, v) in bytes {
+            table.insert(name.to_owned(), toml::Value::Integer(v as i64));
+        }
+        let ratios = vec![("write-amp", self.write_amp()),
+                          ("total-write-amp", self.total_write_amp()),
+                          ("space-amp", self.space_amp()),
+                          ("blocks-per-get", self.blocks_per_get()),
+                          ("disk-blocks-per-get", self.disk_blocks_per_get())];
+        for (name, v) in ratios {
+            if let Some(v) = v {
+                table.insert(name.to_owned(), toml::Value::Float(v));
+            }
+        }
+        toml::Value::Table(table)
+    }
+}
+
+fn fmt_ratio(v: Option<f64>) -> String {
+    v.map_or("n/a".to_owned(), |v| format!("{:.2}", v))
+}
+
+impl fmt::Display for Amplification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f,
+                      "user bytes: {}, wal bytes: {}, flush bytes: {}, compaction bytes: {} \
+                       read {} write",
+                      self.user_bytes,
+                      self.wal_bytes,
+                      self.flush_bytes,
+                      self.compaction_read_bytes,
+                      self.compaction_write_bytes));
+        try!(writeln!(f,
+                      "write amp: {}, with wal: {}",
+                      fmt_ratio(self.write_amp()),
+                      fmt_ratio(self.total_write_amp())));
+        try!(writeln!(f,
+                      "space amp: {} (sst bytes {}, ~{} live keys of {} bytes, live data in \
+                       sst {} bytes)",
+                      fmt_ratio(self.space_amp()),
+                      self.sst_bytes,
+                      self.live_keys,
+                      self.live_logical_bytes,
+                      self.live_data_bytes));
+        if self.keys_read > 0 {
+            try!(writeln!(f,
+                          "read amp: {} blocks per get, {} from disk",
+                          fmt_ratio(self.blocks_per_get()),
+                          fmt_ratio(self.disk_blocks_per_get())));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Amplification;
+
+    #[test]
+    fn test_amplification() {
+        let mut amp = Amplification::default();
+        assert!(amp.write_amp().is_none());
+        assert!(amp.space_amp().is_none());
+
+        amp.user_bytes = 100;
+        amp.wal_bytes = 100;
+        amp.flush_bytes = 100;
+        amp.compaction_write_bytes = 300;
+        amp.sst_bytes = 150;
+        amp.live_data_bytes = 50;
+        amp.live_logical_bytes = 100;
+        assert_eq!(amp.write_amp(), Some(4.0));
+        assert_eq!(amp.total_write_amp(), Some(5.0));
+        assert_eq!(amp.space_amp(), Some(1.5));
+    }
+}
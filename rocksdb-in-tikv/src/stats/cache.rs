@@ -0,0 +1,110 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+use rocksdb::DB;
+
+const ROCKSDB_BLOCK_CACHE_CAPACITY: &'static str = "rocksdb.block-cache-capacity";
+const ROCKSDB_BLOCK_CACHE_USAGE: &'static str = "rocksdb.block-cache-usage";
+const ROCKSDB_BLOCK_CACHE_PINNED_USAGE: &'static str = "rocksdb.block-cache-pinned-usage";
+
+pub struct BlockCacheUsage {
+    pub capacity: u64,
+    pub usage: u64,
+    pub pinned_usage: u64,
+}
+
+impl BlockCacheUsage {
+    fn collect(db: &DB, cf: &str) -> BlockCacheUsage {
+        let handle = db.cf_handle(cf).expect("");
+        let get = |key| db.get_property_int_cf(handle, key).unwrap_or(0);
+        BlockCacheUsage {
+            capacity: get(ROCKSDB_BLOCK_CACHE_CAPACITY),
+            usage: get(ROCKSDB_BLOCK_CACHE_USAGE),
+            pinned_usage: get(ROCKSDB_BLOCK_CACHE_PINNED_USAGE),
+        }
+    }
+
+    fn insert_into(&self, table: &mut BTreeMap<String, toml::Value>) {
+        table.insert("capacity".to_owned(), toml::Value::Integer(self.capacity as i64));
+        table.insert("usage".to_owned(), toml::Value::Integer(self.usage as i64));
+        table.insert("pinned-usage".to_owned(),
+                     toml::Value::Integer(self.pinned_usage as i64));
+    }
+}
+
+impl fmt::Display for BlockCacheUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "usage {} of {}, pinned {}",
+               self.usage,
+               self.capacity,
+               self.pinned_usage)
+    }
+}
+
+// Block cache usage, of the whole cache when it is shared, as every cf reports the same
+// numbers then, or of every cf.
+pub enum CacheUsage {
+    Shared(BlockCacheUsage),
+    PerCf(Vec<(String, BlockCacheUsage)>),
+}
+
+impl CacheUsage {
+    pub fn collect(db: &DB, shared: bool) -> CacheUsage {
+        if shared {
+            return CacheUsage::Shared(BlockCacheUsage::collect(db, "default"));
+        }
+        let cfs = db.cf_names()
+            .into_iter()
+            .map(|name| (name.to_owned(), BlockCacheUsage::collect(db, name)))
+            .collect();
+        CacheUsage::PerCf(cfs)
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        match *self {
+            CacheUsage::Shared(ref usage) => {
+                table.insert("shared".to_owned(), toml::Value::Boolean(true));
+                usage.insert_into(&mut table);
+            }
+            CacheUsage::PerCf(ref cfs) => {
+                table.insert("shared".to_owned(), toml::Value::Boolean(false));
+                for &(ref name, ref usage) in cfs {
+                    let mut cf = BTreeMap::new();
+                    usage.insert_into(&mut cf);
+                    table.insert(name.clone(), toml::Value::Table(cf));
+                }
+            }
+        }
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for CacheUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CacheUsage::Shared(ref usage) => writeln!(f, "block cache (shared): {}", usage),
+            CacheUsage::PerCf(ref cfs) => {
+                try!(writeln!(f, "block cache (per cf):"));
+                for &(ref name, ref usage) in cfs {
+                    try!(writeln!(f, "  cf {}: {}", name, usage));
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::collections::BTreeMap;
+use toml;
+use rocksdb::DB;
+
+const MB: f64 = 1024.0 * 1024.0;
+
+const ROCKSDB_TOTAL_SST_FILES_SIZE: &'static str = "rocksdb.total-sst-files-size";
+const ROCKSDB_LIVE_SST_FILES_SIZE: &'static str = "rocksdb.live-sst-files-size";
+const ROCKSDB_ESTIMATE_NUM_KEYS: &'static str = "rocksdb.estimate-num-keys";
+const ROCKSDB_LEVEL_STATS: &'static str = "rocksdb.levelstats";
+
+// Number of files and their total size.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FileUsage {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl FileUsage {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        let mut t = BTreeMap::new();
+        t.insert("files".to_owned(), toml::Value::Integer(self.files as i64));
+        t.insert("bytes".to_owned(), toml::Value::Integer(self.bytes as i64));
+        toml::Value::Table(t)
+    }
+}
+
+// Files in the db dir (and the wal and info log dirs), by kind.
+#[derive(Debug, Default, PartialEq)]
+pub struct DirUsage {
+    pub sst: FileUsage,
+    pub wal: FileUsage,
+    pub manifest: FileUsage,
+    pub info_log: FileUsage,
+    pub other: FileUsage,
+}
+
+fn classify(usage: &mut DirUsage, name: &str, bytes: u64) {
+    if name.ends_with(".sst") {
+        usage.sst.add(bytes);
+    } else if name.ends_with(".log") {
+        usage.wal.add(bytes);
+    } else if name.starts_with("MANIFEST-") {
+        usage.manifest.add(bytes);
+    } else if name == "LOG" || name.starts_with("LOG.old.") || name.ends_with("_LOG") ||
+              name.contains("_LOG.old.") {
+        // Info logs outside the db dir are prefixed with the db path, eg: "data_db_LOG".
+        usage.info_log.add(bytes);
+    } else {
+        usage.other.add(bytes);
+    }
+}
+
+fn walk_dir(usage: &mut DirUsage, dir: &str, logs_only: bool) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if logs_only && !name.ends_with(".log") && !name.contains("LOG") {
+            continue;
+        }
+        classify(usage, &name, meta.len());
+    }
+}
+
+// Parse "rocksdb.levelstats", whose rows are "level files size(MB)".
+fn parse_level_stats(s: &str) -> Vec<FileUsage> {
+    s.lines()
+        .filter_map(|l| {
+            let f: Vec<&str> = l.split_whitespace().collect();
+            if f.len() != 3 || f[0].parse::<u64>().is_err() {
+                return None;
+            }
+            match (f[1].parse::<u64>(), f[2].parse::<f64>()) {
+                (Ok(files), Ok(mb)) => {
+                    Some(FileUsage {
+                        files: files,
+                        bytes: (mb * MB) as u64,
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub struct CfDiskUsage {
+    pub cf: String,
+    pub total_sst_bytes: u64,
+    pub live_sst_bytes: u64,
+    pub estimate_num_keys: u64,
+    // Level sizes are rounded to MB by RocksDB.
+    pub levels: Vec<FileUsage>,
+}
+
+pub struct DiskUsage {
+    pub dir: DirUsage,
+    pub cfs: Vec<CfDiskUsage>,
+}
+
+impl DiskUsage {
+    // `wal_dir` and `info_log_dir` are walked as well when set and not the db dir.
+    pub fn collect(db: &DB, db_path: &str, wal_dir: &str, info_log_dir: &str) -> DiskUsage {
+        let mut dir = DirUsage::default();
+        walk_dir(&mut dir, db_path, false);
+        let mut walked = vec![Path::new(db_path)];
+        for extra in &[wal_dir, info_log_dir] {
+            let path = Path::new(*extra);
+            if extra.is_empty() || walked.contains(&path) {
+                continue;
+            }
+            walk_dir(&mut dir, extra, true);
+            walked.push(path);
+        }
+
+        let cfs = db.cf_names()
+            .into_iter()
+            .map(|name| {
+                let handle = db.cf_handle(name).expect("");
+                let get = |key| db.get_property_int_cf(handle, key).unwrap_or(0);
+                CfDiskUsage {
+                    cf: name.to_owned(),
+                    total_sst_bytes: get(ROCKSDB_TOTAL_SST_FILES_SIZE),
+                    live_sst_bytes: get(ROCKSDB_LIVE_SST_FILES_SIZE),
+                    estimate_num_keys: get(ROCKSDB_ESTIMATE_NUM_KEYS),
+                    levels: db.get_property_value_cf(handle, ROCKSDB_LEVEL_STATS)
+                        .map_or(vec![], |s| parse_level_stats(&s)),
+                }
+            })
+            .collect();
+        DiskUsage {
+            dir: dir,
+            cfs: cfs,
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut dir = BTreeMap::new();
+        dir.insert("sst".to_owned(), self.dir.sst.to_toml());
+        dir.insert("wal".to_owned(), self.dir.wal.to_toml());
+        dir.insert("manifest".to_owned(), self.dir.manifest.to_toml());
+        dir.insert("info-log".to_owned(), self.dir.info_log.to_toml());
+        dir.insert("other".to_owned(), self.dir.other.to_toml());
+
+        let mut cfs = BTreeMap::new();
+        for c in &self.cfs {
+            let mut cf = BTreeMap::new();
+            cf.insert("total-sst-bytes".to_owned(),
+                      toml::Value::Integer(c.total_sst_bytes as i64));
+            cf.insert("live-sst-bytes".to_owned(),
+                      toml::Value::Integer(c.live_sst_bytes as i64));
+            cf.insert("estimate-num-keys".to_owned(),
+                      toml::Value::Integer(c.estimate_num_keys as i64));
+            cf.insert("levels".to_owned(),
+                      toml::Value::Array(c.levels.iter().map(|l| l.to_toml()).collect()));
+            cfs.insert(c.cf.clone(), toml::Value::Table(cf));
+        }
+
+        let mut table = BTreeMap::new();
+        table.insert("dir".to_owned(), toml::Value::Table(dir));
+        table.insert("cf".to_owned(), toml::Value::Table(cfs));
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for DiskUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let d = &self.dir;
+        try!(writeln!(f,
+                      "disk usage: sst {} bytes in {} files, wal {} bytes in {} files, manifest \
+                       {} bytes, info log {} bytes, other {} bytes",
+                      d.sst.bytes,
+                      d.sst.files,
+                      d.wal.bytes,
+                      d.wal.files,
+                      d.manifest.bytes,
+                      d.info_log.bytes,
+                      d.other.bytes));
+        for c in &self.cfs {
+            try!(writeln!(f,
+                          "  cf {}: sst {} live / {} total bytes, ~{} keys",
+                          c.cf,
+                          c.live_sst_bytes,
+                          c.total_sst_bytes,
+                          c.estimate_num_keys));
+            for (level, l) in c.levels.iter().enumerate() {
+                if l.files > 0 {
+                    try!(writeln!(f, "    L{}: {} files, ~{} bytes", level, l.files, l.bytes));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DirUsage, classify, parse_level_stats};
+
+    #[test]
+    fn test_disk_usage() {
+        let stats = "Level Files Size(MB)\n--------------------\n  0        2        1\n  1        \
+                     4       93\n";
+        let levels = parse_level_stats(stats);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[1].files, 4);
+        assert_eq!(levels[1].bytes, 93 * 1024 * 1024);
+
+        let mut usage = DirUsage::default();
+        for &(name, bytes) in &[("000012.sst", 10),
+                                ("000013.sst", 20),
+                                ("000014.log", 5),
+                                ("MANIFEST-000005", 3),
+                                ("LOG", 2),
+                                ("LOG.old.1500000000", 1),
+                                ("CURRENT", 16)] {
+            classify(&mut usage, name, bytes);
+        }
+        assert_eq!((usage.sst.files, usage.sst.bytes), (2, 30));
+        assert_eq!((usage.wal.files, usage.wal.bytes), (1, 5));
+        assert_eq!(usage.manifest.bytes, 3);
+        assert_eq!((usage.info_log.files, usage.info_log.bytes), (2, 3));
+        assert_eq!(usage.other.bytes, 16);
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod statistics;
+pub mod amp;
+pub mod stall;
+pub mod cache;
+pub mod os;
+pub mod disk;
+
+use std::fs::File;
+use std::io::Write;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use toml;
+use rocksdb::DB;
+
+use self::statistics::Statistics;
+use self::stall::{StallTracker, StallTimeline};
+use self::os::{OsSnapshot, OsUsage};
+
+const SAMPLER_TICK_MS: u64 = 100;
+
+// Structured form of a run, one toml table per section.
+pub struct Report {
+    sections: BTreeMap<String, toml::Value>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report { sections: BTreeMap::new() }
+    }
+
+    pub fn insert(&mut self, name: &str, section: toml::Value) {
+        self.sections.insert(name.to_owned(), section);
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        toml::Value::Table(self.sections.clone())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut f = try!(File::create(path)
+            .map_err(|e| format!("create report {} failed: {}", path, e)));
+        f.write_all(self.to_toml().to_string().as_bytes())
+            .map_err(|e| format!("write report {} failed: {}", path, e))
+    }
+}
+
+// What the sampler saw while the workload was running.
+pub struct Samples {
+    pub intervals: Vec<Statistics>,
+    pub os_intervals: Vec<OsUsage>,
+    // Over the whole run.
+    pub os: OsUsage,
+    pub stalls: StallTimeline,
+}
+
+// Tracks write stalls and the os usage in the background, and if `interval` is given,
+// prints the tickers and os usage changed in each interval. `disk` is the device of
+// the db, eg: "sda".
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Samples>,
+}
+
+impl Sampler {
+    pub fn start(db: Arc<DB>, interval: Option<Duration>, disk: Option<String>) -> Sampler {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut intervals = vec![];
+            let mut os_intervals = vec![];
+            let mut stalls = StallTracker::new(&db);
+            let mut prev = Statistics::collect(&db);
+            let os_start = OsSnapshot::collect(disk.as_ref().map(|d| d.as_str()));
+            let mut os_prev = os_start;
+            let mut last = Instant::now();
+            while !stopped.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(SAMPLER_TICK_MS));
+                stalls.sample(&db);
+
+                let interval = match interval {
+                    Some(interval) => interval,
+                    None => continue,
+                };
+                if last.elapsed() < interval {
+                    continue;
+                }
+                last = Instant::now();
+                let curr = Statistics::collect(&db);
+                let delta = curr.delta(&prev);
+                println!("interval {}:", intervals.len());
+                print!("{}", delta);
+                intervals.push(delta);
+                prev = curr;
+                let os_curr = OsSnapshot::collect(disk.as_ref().map(|d| d.as_str()));
+                let os_delta = OsUsage::between(&os_prev, &os_curr);
+                print!("{}", os_delta);
+                os_intervals.push(os_delta);
+                os_prev = os_curr;
+            }
+            let os_end = OsSnapshot::collect(disk.as_ref().map(|d| d.as_str()));
+            Samples {
+                intervals: intervals,
+                os_intervals: os_intervals,
+                os: OsUsage::between(&os_start, &os_end),
+                stalls: stalls.finish(),
+            }
+        });
+        Sampler {
+            stop: stop,
+            handle: handle,
+        }
+    }
+
+    pub fn stop(self) -> Result<Samples, String> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().map_err(|_| "sampler thread panicked".to_owned())
+    }
+}
+
+// The tickers of every interval, with the os usage in it as `os`.
+pub fn intervals_to_toml(samples: &Samples) -> toml::Value {
+    let intervals = samples.intervals
+        .iter()
+        .zip(samples.os_intervals.iter())
+        .map(|(s, os)| {
+            let mut t = s.to_toml();
+            if let toml::Value::Table(ref mut t) = t {
+                t.insert("os".to_owned(), os.to_toml());
+            }
+            t
+        })
+        .collect();
+    toml::Value::Array(intervals)
+}
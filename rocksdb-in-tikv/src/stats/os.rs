@@ -0,0 +1,278 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// What the OS saw of the process and of the disk under the db, to tell whether a
+// run was disk or cpu bound.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use toml;
+
+use env::host;
+
+const SECTOR_BYTES: u64 = 512;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProcIo {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+// Bytes that really hit the storage layer, from /proc/self/io.
+fn parse_proc_io(s: &str) -> ProcIo {
+    let mut io = ProcIo::default();
+    for line in s.lines() {
+        let mut kv = line.splitn(2, ':');
+        let (k, v) = match (kv.next(), kv.next().and_then(|v| v.trim().parse().ok())) {
+            (Some(k), Some(v)) => (k, v),
+            _ => continue,
+        };
+        match k {
+            "read_bytes" => io.read_bytes = v,
+            "write_bytes" => io.write_bytes = v,
+            _ => {}
+        }
+    }
+    io
+}
+
+// utime and stime of /proc/self/stat, in clock ticks. The fields are counted after
+// the command name, which may have spaces in it.
+fn parse_proc_stat(s: &str) -> Option<(u64, u64)> {
+    let fields: Vec<&str> = match s.rfind(')') {
+        Some(i) => s[i + 1..].split_whitespace().collect(),
+        None => return None,
+    };
+    match (fields.get(11).and_then(|f| f.parse().ok()),
+           fields.get(12).and_then(|f| f.parse().ok())) {
+        (Some(utime), Some(stime)) => Some((utime, stime)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiskStats {
+    pub reads: u64,
+    pub read_sectors: u64,
+    pub writes: u64,
+    pub write_sectors: u64,
+    pub io_ms: u64,
+}
+
+fn parse_diskstats(s: &str, disk: &str) -> Option<DiskStats> {
+    s.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<&str>>())
+        .find(|f| f.len() >= 13 && f[2] == disk)
+        .map(|f| {
+            let n = |i: usize| f[i].parse().unwrap_or(0);
+            DiskStats {
+                reads: n(3),
+                read_sectors: n(5),
+                writes: n(7),
+                write_sectors: n(9),
+                io_ms: n(12),
+            }
+        })
+}
+
+#[cfg(unix)]
+fn clock_ticks_per_sec() -> u64 {
+    use libc;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 { ticks as u64 } else { 100 }
+}
+
+// Context switches of all the threads, the exited ones included.
+#[cfg(unix)]
+fn context_switches() -> u64 {
+    use std::mem;
+    use libc;
+    unsafe {
+        let mut usage: libc::rusage = mem::zeroed();
+        if libc::getrusage(libc::RUSAGE_SELF, &mut usage) != 0 {
+            return 0;
+        }
+        (usage.ru_nvcsw + usage.ru_nivcsw) as u64
+    }
+}
+
+#[cfg(not(unix))]
+fn clock_ticks_per_sec() -> u64 {
+    100
+}
+
+#[cfg(not(unix))]
+fn context_switches() -> u64 {
+    0
+}
+
+#[derive(Clone, Copy)]
+pub struct OsSnapshot {
+    at: Instant,
+    io: ProcIo,
+    cpu_ticks: (u64, u64),
+    context_switches: u64,
+    disk: Option<DiskStats>,
+}
+
+impl OsSnapshot {
+    // `disk` is the device under the db, as named in /proc/diskstats, eg: "sda".
+    pub fn collect(disk: Option<&str>) -> OsSnapshot {
+        OsSnapshot {
+            at: Instant::now(),
+            io: host::read_trimmed("/proc/self/io").map_or(ProcIo::default(),
+                                                          |s| parse_proc_io(&s)),
+            cpu_ticks: host::read_trimmed("/proc/self/stat")
+                .and_then(|s| parse_proc_stat(&s))
+                .unwrap_or((0, 0)),
+            context_switches: context_switches(),
+            disk: disk.and_then(|d| {
+                host::read_trimmed("/proc/diskstats").and_then(|s| parse_diskstats(&s, d))
+            }),
+        }
+    }
+}
+
+fn per_sec(n: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    if secs == 0.0 { 0.0 } else { n as f64 / secs }
+}
+
+// The change between two snapshots. Disk counters are of the whole device, other
+// processes on it included.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsUsage {
+    pub elapsed: Duration,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub user_cpu_ms: u64,
+    pub sys_cpu_ms: u64,
+    pub context_switches: u64,
+    pub disk: Option<DiskStats>,
+}
+
+impl OsUsage {
+    pub fn between(prev: &OsSnapshot, curr: &OsSnapshot) -> OsUsage {
+        let ticks_ms = |t: u64| t * 1000 / clock_ticks_per_sec();
+        OsUsage {
+            elapsed: curr.at.duration_since(prev.at),
+            read_bytes: curr.io.read_bytes.saturating_sub(prev.io.read_bytes),
+            write_bytes: curr.io.write_bytes.saturating_sub(prev.io.write_bytes),
+            user_cpu_ms: ticks_ms(curr.cpu_ticks.0.saturating_sub(prev.cpu_ticks.0)),
+            sys_cpu_ms: ticks_ms(curr.cpu_ticks.1.saturating_sub(prev.cpu_ticks.1)),
+            context_switches: curr.context_switches.saturating_sub(prev.context_switches),
+            disk: match (prev.disk, curr.disk) {
+                (Some(p), Some(c)) => {
+                    Some(DiskStats {
+                        reads: c.reads.saturating_sub(p.reads),
+                        read_sectors: c.read_sectors.saturating_sub(p.read_sectors),
+                        writes: c.writes.saturating_sub(p.writes),
+                        write_sectors: c.write_sectors.saturating_sub(p.write_sectors),
+                        io_ms: c.io_ms.saturating_sub(p.io_ms),
+                    })
+                }
+                _ => None,
+            },
+        }
+    }
+
+    pub fn read_iops(&self) -> Option<f64> {
+        self.disk.map(|d| per_sec(d.reads, self.elapsed))
+    }
+
+    pub fn write_iops(&self) -> Option<f64> {
+        self.disk.map(|d| per_sec(d.writes, self.elapsed))
+    }
+
+    // Share of the time the device was busy, in percent.
+    pub fn disk_util(&self) -> Option<f64> {
+        let elapsed_ms = self.elapsed.as_secs() * 1000 +
+                         self.elapsed.subsec_nanos() as u64 / 1_000_000;
+        self.disk.map(|d| if elapsed_ms == 0 {
+            0.0
+        } else {
+            (d.io_ms as f64 / elapsed_ms as f64 * 100.0).min(100.0)
+        })
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        let counters = vec![("read-bytes", self.read_bytes),
+                            ("write-bytes", self.write_bytes),
+                            ("user-cpu-ms", self.user_cpu_ms),
+                            ("sys-cpu-ms", self.sys_cpu_ms),
+                            ("context-switches", self.context_switches)];
+        for (name, v) in counters {
+            table.insert(name.to_owned(), toml::Value::Integer(v as i64));
+        }
+        if let Some(d) = self.disk {
+            table.insert("disk-read-bytes".to_owned(),
+                         toml::Value::Integer((d.read_sectors * SECTOR_BYTES) as i64));
+            table.insert("disk-write-bytes".to_owned(),
+                         toml::Value::Integer((d.write_sectors * SECTOR_BYTES) as i64));
+        }
+        let rates = vec![("read-iops", self.read_iops()),
+                         ("write-iops", self.write_iops()),
+                         ("disk-util", self.disk_util())];
+        for (name, v) in rates {
+            if let Some(v) = v {
+                table.insert(name.to_owned(), toml::Value::Float(v));
+            }
+        }
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for OsUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f,
+                      "os: read {} bytes, write {} bytes, cpu {} ms user {} ms sys, {} context \
+                       switches",
+                      self.read_bytes,
+                      self.write_bytes,
+                      self.user_cpu_ms,
+                      self.sys_cpu_ms,
+                      self.context_switches));
+        if let Some(d) = self.disk {
+            try!(writeln!(f,
+                          "disk: read {} bytes {:.0} iops, write {} bytes {:.0} iops, util {:.1}%",
+                          d.read_sectors * SECTOR_BYTES,
+                          self.read_iops().unwrap_or(0.0),
+                          d.write_sectors * SECTOR_BYTES,
+                          self.write_iops().unwrap_or(0.0),
+                          self.disk_util().unwrap_or(0.0)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_diskstats, parse_proc_io, parse_proc_stat};
+
+    #[test]
+    fn test_parse_proc() {
+        let io = parse_proc_io("rchar: 3980\nwchar: 0\nread_bytes: 4096\nwrite_bytes: 8192\n");
+        assert_eq!((io.read_bytes, io.write_bytes), (4096, 8192));
+        let stat = "9692 (a b) R 9686 9692 9686 0 -1 4194304 83 0 0 0 25 7 0 0 20 0 1 0";
+        assert_eq!(parse_proc_stat(stat), Some((25, 7)));
+        let disk = parse_diskstats("   7 0 loop0 0 0 0 0 0 0 0 0 0 0 0\n \
+                                    253 0 vda 10 0 80 5 20 0 160 9 0 30 14\n",
+                                   "vda")
+            .unwrap();
+        assert_eq!((disk.reads, disk.read_sectors, disk.writes, disk.write_sectors),
+                   (10, 80, 20, 160));
+        assert_eq!(disk.io_ms, 30);
+    }
+}
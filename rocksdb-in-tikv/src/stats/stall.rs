@@ -0,0 +1,252 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use toml;
+use rocksdb::DB;
+
+const ROCKSDB_DELAYED_WRITE_RATE: &'static str = "rocksdb.actual-delayed-write-rate";
+const ROCKSDB_IS_WRITE_STOPPED: &'static str = "rocksdb.is-write-stopped";
+const ROCKSDB_CF_STATS_KEY: &'static str = "rocksdb.cfstats";
+const STALL_COUNTERS_PREFIX: &'static str = "Stalls(count):";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StallCause {
+    L0Files,
+    PendingCompactionBytes,
+    Memtables,
+    Unknown,
+}
+
+impl StallCause {
+    fn from_counter(name: &str) -> StallCause {
+        if name.contains("level0") {
+            StallCause::L0Files
+        } else if name.contains("pending_compaction_bytes") {
+            StallCause::PendingCompactionBytes
+        } else if name.contains("memtable") {
+            StallCause::Memtables
+        } else {
+            StallCause::Unknown
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            StallCause::L0Files => "l0-files",
+            StallCause::PendingCompactionBytes => "pending-compaction-bytes",
+            StallCause::Memtables => "memtable-count",
+            StallCause::Unknown => "unknown",
+        }
+    }
+}
+
+pub struct Stall {
+    pub start: Duration,
+    pub end: Duration,
+    pub stopped: bool,
+    pub cause: StallCause,
+}
+
+// Parse the "Stalls(count): N name, N name, ..." line out of `rocksdb.cfstats`.
+fn parse_stall_counters(cf_stats: &str, counters: &mut BTreeMap<String, u64>) {
+    for line in cf_stats.lines() {
+        if !line.starts_with(STALL_COUNTERS_PREFIX) {
+            continue;
+        }
+        for item in line[STALL_COUNTERS_PREFIX.len()..].split(',') {
+            let item = item.trim();
+            let mut parts = item.splitn(2, ' ');
+            let n = match parts.next().and_then(|n| n.parse::<u64>().ok()) {
+                Some(n) => n,
+                None => continue,
+            };
+            match parts.next() {
+                // "interval N total count" is a sum of the others.
+                Some(name) if !name.contains("total count") => {
+                    *counters.entry(name.to_owned()).or_insert(0) += n;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn stall_counters(db: &DB) -> BTreeMap<String, u64> {
+    let mut counters = BTreeMap::new();
+    for name in db.cf_names() {
+        let handle = db.cf_handle(name).expect("");
+        if let Some(cf_stats) = db.get_property_value_cf(handle, ROCKSDB_CF_STATS_KEY) {
+            parse_stall_counters(&cf_stats, &mut counters);
+        }
+    }
+    counters
+}
+
+// The counter that grew the most since `prev` tells why we are stalling.
+fn stall_cause(prev: &BTreeMap<String, u64>, curr: &BTreeMap<String, u64>) -> StallCause {
+    let mut cause = StallCause::Unknown;
+    let mut max = 0;
+    for (name, &n) in curr {
+        let delta = n.saturating_sub(*prev.get(name).unwrap_or(&0));
+        if delta > max {
+            max = delta;
+            cause = StallCause::from_counter(name);
+        }
+    }
+    cause
+}
+
+pub struct StallTracker {
+    start: Instant,
+    counters: BTreeMap<String, u64>,
+    current: Option<Stall>,
+    stalls: Vec<Stall>,
+}
+
+impl StallTracker {
+    pub fn new(db: &DB) -> StallTracker {
+        StallTracker {
+            start: Instant::now(),
+            counters: stall_counters(db),
+            current: None,
+            stalls: vec![],
+        }
+    }
+
+    // The stall counters are only read when a stall starts and ends: formatting cfstats
+    // takes the db mutex, and every read moves its interval stats.
+    pub fn sample(&mut self, db: &DB) {
+        let now = self.start.elapsed();
+        let delayed = db.get_property_int(ROCKSDB_DELAYED_WRITE_RATE).unwrap_or(0) > 0;
+        let stopped = db.get_property_int(ROCKSDB_IS_WRITE_STOPPED).unwrap_or(0) > 0;
+        if !delayed && !stopped {
+            if let Some(mut stall) = self.current.take() {
+                stall.end = now;
+                let counters = stall_counters(db);
+                if stall.cause == StallCause::Unknown {
+                    stall.cause = stall_cause(&self.counters, &counters);
+                }
+                self.counters = counters;
+                self.stalls.push(stall);
+            }
+            return;
+        }
+
+        match self.current {
+            Some(ref mut stall) => {
+                stall.end = now;
+                stall.stopped |= stopped;
+            }
+            None => {
+                let counters = stall_counters(db);
+                let cause = stall_cause(&self.counters, &counters);
+                self.counters = counters;
+                self.current = Some(Stall {
+                    start: now,
+                    end: now,
+                    stopped: stopped,
+                    cause: cause,
+                })
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> StallTimeline {
+        if let Some(stall) = self.current.take() {
+            self.stalls.push(stall);
+        }
+        StallTimeline { stalls: self.stalls }
+    }
+}
+
+fn duration_ms(d: Duration) -> u64 {
+    d.as_secs() * 1000 + d.subsec_nanos() as u64 / 1_000_000
+}
+
+pub struct StallTimeline {
+    pub stalls: Vec<Stall>,
+}
+
+impl StallTimeline {
+    pub fn total_ms(&self) -> u64 {
+        self.stalls.iter().map(|s| duration_ms(s.end) - duration_ms(s.start)).sum()
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let stalls = self.stalls
+            .iter()
+            .map(|s| {
+                let mut stall = BTreeMap::new();
+                stall.insert("start-ms".to_owned(),
+                             toml::Value::Integer(duration_ms(s.start) as i64));
+                stall.insert("end-ms".to_owned(),
+                             toml::Value::Integer(duration_ms(s.end) as i64));
+                stall.insert("stopped".to_owned(), toml::Value::Boolean(s.stopped));
+                stall.insert("cause".to_owned(),
+                             toml::Value::String(s.cause.name().to_owned()));
+                toml::Value::Table(stall)
+            })
+            .collect();
+        let mut table = BTreeMap::new();
+        table.insert("total-ms".to_owned(),
+                     toml::Value::Integer(self.total_ms() as i64));
+        table.insert("timeline".to_owned(), toml::Value::Array(stalls));
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for StallTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for s in &self.stalls {
+            try!(writeln!(f,
+                          "stall: {} ms - {} ms, {}, cause: {}",
+                          duration_ms(s.start),
+                          duration_ms(s.end),
+                          if s.stopped { "stopped" } else { "delayed" },
+                          s.cause.name()));
+        }
+        writeln!(f,
+                 "stalls: {} times, {} ms in total",
+                 self.stalls.len(),
+                 self.total_ms())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use super::{parse_stall_counters, stall_cause, StallCause};
+
+    #[test]
+    fn test_parse_stall_counters() {
+        let cf_stats = "Flush(GB): cumulative 0.000, interval 0.000\n\
+                        Stalls(count): 2 level0_slowdown, 0 level0_slowdown_with_compaction, \
+                        1 level0_numfiles, 0 level0_numfiles_with_compaction, \
+                        0 stop for pending_compaction_bytes, \
+                        4 slowdown for pending_compaction_bytes, 0 memtable_compaction, \
+                        0 memtable_slowdown, interval 7 total count\n";
+        let mut prev = BTreeMap::new();
+        parse_stall_counters(cf_stats, &mut prev);
+        assert_eq!(prev["level0_slowdown"], 2);
+        assert_eq!(prev["slowdown for pending_compaction_bytes"], 4);
+        assert!(!prev.keys().any(|k| k.contains("total count")));
+
+        let mut curr = prev.clone();
+        *curr.get_mut("memtable_slowdown").unwrap() += 3;
+        assert_eq!(stall_cause(&prev, &curr), StallCause::Memtables);
+        assert_eq!(stall_cause(&prev, &prev), StallCause::Unknown);
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use toml;
+use rocksdb::{DB, DBStatisticsTickerType as Ticker, DBStatisticsHistogramType as Histogram,
+              HistogramData};
+
+// The tickers we look at after a tuning run.
+const TICKERS: &'static [(&'static str, Ticker)] = &[
+    ("block-cache-hit", Ticker::BlockCacheHit),
+    ("block-cache-miss", Ticker::BlockCacheMiss),
+    ("block-cache-index-hit", Ticker::BlockCacheIndexHit),
+    ("block-cache-index-miss", Ticker::BlockCacheIndexMiss),
+    ("block-cache-filter-hit", Ticker::BlockCacheFilterHit),
+    ("block-cache-filter-miss", Ticker::BlockCacheFilterMiss),
+    ("block-cache-data-hit", Ticker::BlockCacheDataHit),
+    ("block-cache-data-miss", Ticker::BlockCacheDataMiss),
+    ("bloom-filter-useful", Ticker::BloomFilterUseful),
+    ("bloom-filter-prefix-checked", Ticker::BloomFilterPrefixChecked),
+    ("bloom-filter-prefix-useful", Ticker::BloomFilterPrefixUseful),
+    ("number-keys-written", Ticker::NumberKeysWritten),
+    ("number-keys-read", Ticker::NumberKeysRead),
+    ("bytes-written", Ticker::BytesWritten),
+    ("bytes-read", Ticker::BytesRead),
+    ("compact-read-bytes", Ticker::CompactReadBytes),
+    ("compact-write-bytes", Ticker::CompactWriteBytes),
+    ("flush-write-bytes", Ticker::FlushWriteBytes),
+    ("stall-micros", Ticker::StallMicros),
+    ("wal-file-synced", Ticker::WalFileSynced),
+    ("wal-file-bytes", Ticker::WalFileBytes),
+];
+
+const HISTOGRAMS: &'static [(&'static str, Histogram)] = &[
+    ("db-get-micros", Histogram::GetMicros),
+    ("db-write-micros", Histogram::WriteMicros),
+    ("db-seek-micros", Histogram::SeekMicros),
+    ("wal-file-sync-micros", Histogram::WalFileSyncMicros),
+];
+
+pub struct Statistics {
+    pub tickers: Vec<(&'static str, u64)>,
+    pub histograms: Vec<(&'static str, HistogramData)>,
+}
+
+impl Statistics {
+    pub fn collect(db: &DB) -> Statistics {
+        let tickers = TICKERS.iter()
+            .map(|&(name, tp)| (name, db.get_statistics_ticker_count(tp)))
+            .collect();
+        let mut histograms = Vec::with_capacity(HISTOGRAMS.len());
+        for &(name, tp) in HISTOGRAMS {
+            if let Some(data) = db.get_statistics_histogram(tp) {
+                histograms.push((name, data));
+            }
+        }
+        Statistics {
+            tickers: tickers,
+            histograms: histograms,
+        }
+    }
+
+    pub fn ticker(&self, name: &str) -> u64 {
+        self.tickers.iter().find(|&&(n, _)| n == name).map_or(0, |&(_, v)| v)
+    }
+
+    pub fn histogram(&self, name: &str) -> Option<&HistogramData> {
+        self.histograms.iter().find(|&&(n, _)| n == name).map(|&(_, ref v)| v)
+    }
+
+    // Ticker changes since `prev`, histograms are not interval-able and left out.
+    pub fn delta(&self, prev: &Statistics) -> Statistics {
+        let tickers = self.tickers
+            .iter()
+            .map(|&(name, v)| (name, v.saturating_sub(prev.ticker(name))))
+            .collect();
+        Statistics {
+            tickers: tickers,
+            histograms: vec![],
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut tickers = BTreeMap::new();
+        for &(name, v) in &self.tickers {
+            tickers.insert(name.to_owned(), toml::Value::Integer(v as i64));
+        }
+        let mut histograms = BTreeMap::new();
+        for &(name, ref data) in &self.histograms {
+            let mut hist = BTreeMap::new();
+            hist.insert("median".to_owned(), toml::Value::Float(data.median));
+            hist.insert("p95".to_owned(), toml::Value::Float(data.percentile95));
+            hist.insert("p99".to_owned(), toml::Value::Float(data.percentile99));
+            hist.insert("average".to_owned(), toml::Value::Float(data.average));
+            hist.insert("stddev".to_owned(),
+                        toml::Value::Float(data.standard_deviation));
+            histograms.insert(name.to_owned(), toml::Value::Table(hist));
+        }
+        let mut table = BTreeMap::new();
+        table.insert("tickers".to_owned(), toml::Value::Table(tickers));
+        table.insert("histograms".to_owned(), toml::Value::Table(histograms));
+        toml::Value::Table(table)
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(name, v) in &self.tickers {
+            try!(writeln!(f, "{}: {}", name, v));
+        }
+        for &(name, ref data) in &self.histograms {
+            try!(writeln!(f,
+                          "{}: P50 {:.2} P95 {:.2} P99 {:.2} AVG {:.2} STDDEV {:.2}",
+                          name,
+                          data.median,
+                          data.percentile95,
+                          data.percentile99,
+                          data.average,
+                          data.standard_deviation));
+        }
+        Ok(())
+    }
+}